@@ -75,7 +75,7 @@ mod modifiers {
         };
 
         let n: usize = match (*args
-            .get(0)
+            .first()
             .unwrap_or(&&mini_template::value::Value::Number(2.)))
         .try_into()
         {
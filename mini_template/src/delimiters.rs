@@ -0,0 +1,222 @@
+/// Strings opening and closing a tag.
+///
+/// The default is `{` and `}`. Delimiters like `{{` and `}}` or `<%` and `%>` are useful if the
+/// template text contains many braces, e.g. JSON, CSS or LaTeX. Everything else about the
+/// template syntax stays the same, so a comment is written as `<%* comment *%>` and trim markers
+/// as `<%-` and `-%>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delimiters {
+    open: String,
+    close: String,
+}
+
+impl Delimiters {
+    /// # Panics
+    /// Panics if a delimiter is empty.
+    pub fn new(open: &str, close: &str) -> Self {
+        assert!(
+            !open.is_empty() && !close.is_empty(),
+            "tag delimiters must not be empty"
+        );
+        Self {
+            open: open.to_owned(),
+            close: close.to_owned(),
+        }
+    }
+
+    pub fn open(&self) -> &str {
+        &self.open
+    }
+
+    pub fn close(&self) -> &str {
+        &self.close
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        self.open == "{" && self.close == "}"
+    }
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Self::new("{", "}")
+    }
+}
+
+/// A template rewritten to use `{` and `}`, so the grammar does not depend on the delimiters.
+///
+/// Only the delimiters change their length. Braces in the text and in comments are replaced by
+/// `_`, so they are not mistaken for tags. Everything else is copied unchanged.
+#[derive(Debug)]
+pub(crate) struct Translation {
+    pub input: String,
+    /// `(input offset, source offset)` pairs after which both offsets advance together
+    offsets: Vec<(usize, usize)>,
+}
+
+impl Translation {
+    pub fn new(source: &str, delimiters: &Delimiters) -> Self {
+        let mut translation = Self {
+            input: String::with_capacity(source.len()),
+            offsets: vec![(0, 0)],
+        };
+        let open = delimiters.open();
+        let mut rest = source;
+        while let Some(tag) = rest.find(open) {
+            translation.push_text(&rest[..tag]);
+            rest = &rest[tag..];
+            if translation.input.ends_with('\\') {
+                // An escaped tag is text, like `\{` with the default delimiters
+                translation.input.push('{');
+                translation.input.push_str(&"_".repeat(open.len() - 1));
+                rest = &rest[open.len()..];
+                continue;
+            }
+            rest = translation.replace(source, rest, open, "{");
+            if rest.trim_start_matches('-').starts_with('*') {
+                rest = translation.comment(source, rest, delimiters.close());
+                continue;
+            }
+            let tag = translation.input.len();
+            rest = translation.tag(source, rest, delimiters.close());
+            let tag = translation.input[tag..]
+                .strip_suffix('}')
+                .unwrap_or_default();
+            if tag.trim_matches('-').trim() == "literal" {
+                rest = translation.literal(source, rest, delimiters);
+            }
+        }
+        translation.push_text(rest);
+        translation
+    }
+
+    /// Returns the offset in the source for an offset in the translated input.
+    pub fn source_offset(&self, offset: usize) -> usize {
+        let i = self.offsets.partition_point(|(input, _)| *input <= offset) - 1;
+        let (input, source) = self.offsets[i];
+        source + (offset - input)
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.input.extend(
+            text.chars()
+                .map(|c| if c == '{' || c == '}' { '_' } else { c }),
+        );
+    }
+
+    /// Replaces `delimiter` at the start of `rest`, a suffix of `source`, and returns the text
+    /// after it.
+    fn replace<'s>(
+        &mut self,
+        source: &str,
+        rest: &'s str,
+        delimiter: &str,
+        replacement: &str,
+    ) -> &'s str {
+        self.input.push_str(replacement);
+        let offset = source.len() - rest.len() + delimiter.len();
+        self.offsets.push((self.input.len(), offset));
+        &rest[delimiter.len()..]
+    }
+
+    /// Copies a tag up to its closing delimiter and returns the text after it.
+    fn tag<'s>(&mut self, source: &str, mut rest: &'s str, close: &str) -> &'s str {
+        let mut in_string = false;
+        while let Some(c) = rest.chars().next() {
+            if !in_string && rest.starts_with(close) {
+                return self.replace(source, rest, close, "}");
+            }
+            let mut len = c.len_utf8();
+            match c {
+                '"' => in_string = !in_string,
+                '\\' if in_string => len += rest[1..].chars().next().map_or(0, char::len_utf8),
+                _ => {}
+            }
+            self.input.push_str(&rest[..len]);
+            rest = &rest[len..];
+        }
+        rest
+    }
+
+    /// Copies the content of a `{literal}` block and its end tag and returns the text after it.
+    fn literal<'s>(&mut self, source: &str, rest: &'s str, delimiters: &Delimiters) -> &'s str {
+        let open = delimiters.open();
+        let end = rest.match_indices(open).map(|(i, _)| i).find(|i| {
+            let tag = rest[i + open.len()..].trim_start_matches('-').trim_start();
+            tag.strip_prefix('/')
+                .is_some_and(|tag| tag.trim_start().starts_with("literal"))
+        });
+        let Some(end) = end else {
+            // The parser reports the missing end
+            self.push_text(rest);
+            return "";
+        };
+        self.push_text(&rest[..end]);
+        let rest = self.replace(source, &rest[end..], open, "{");
+        self.tag(source, rest, delimiters.close())
+    }
+
+    /// Copies a comment like `* comment *}` and returns the text after it.
+    fn comment<'s>(&mut self, source: &str, rest: &'s str, close: &str) -> &'s str {
+        let (marker, content) = rest.split_at(rest.find('*').unwrap() + 1);
+        self.input.push_str(marker);
+        let end = content.match_indices('*').find_map(|(i, _)| {
+            let after = &content[i + 1..];
+            let end = after.strip_prefix('-').unwrap_or(after);
+            end.starts_with(close).then(|| content.len() - end.len())
+        });
+        let Some(end) = end else {
+            // The parser reports the missing end
+            self.push_text(content);
+            return "";
+        };
+        let (content, rest) = content.split_at(end);
+        self.push_text(content);
+        self.replace(source, rest, close, "}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Delimiters, Translation};
+
+    fn translate(source: &str) -> Translation {
+        Translation::new(source, &Delimiters::new("<%", "%>"))
+    }
+
+    #[test]
+    fn translate_tags() {
+        let t = translate(r#"{"a": <%value|default:"%>"%>}"#);
+        assert_eq!(t.input, r#"_"a": {value|default:"%>"}_"#);
+        assert_eq!(t.source_offset(7), 8);
+        assert_eq!(t.source_offset(25), 26);
+        assert_eq!(t.source_offset(26), 28);
+    }
+
+    #[test]
+    fn translate_comments_and_trim_markers() {
+        let t = translate("<%-* {x} *-%> <%- name -%>");
+        assert_eq!(t.input, "{-* _x_ *-} {- name -}");
+        assert_eq!(t.source_offset(13), 16);
+    }
+
+    #[test]
+    fn translate_literal_block() {
+        let t = translate(r#"<%literal%>" <%x%> \<%-/literal-%>"#);
+        assert_eq!(t.input, r#"{literal}" <%x%> \{-/literal-}"#);
+        assert_eq!(t.source_offset(19), 22);
+    }
+
+    #[test]
+    fn translate_escaped_tag() {
+        let t = translate(r"\<%name%>");
+        assert_eq!(t.input, r"\{_name%>");
+        assert_eq!(t.source_offset(8), 8);
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_delimiter() {
+        Delimiters::new("", "}");
+    }
+}
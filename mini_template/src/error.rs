@@ -1,24 +1,348 @@
 use std::fmt::Display;
 
+use crate::template::{Substr, Template};
+
 pub type Result<'t, T> = std::result::Result<T, Error<'t>>;
 
+#[derive(Debug)]
+pub struct Error<'t> {
+    pub kind: ErrorKind<'t>,
+    /// Part of the template source the error occurred in
+    pub span: Option<Substr>,
+    /// The template whose source contains `span`. Set when the error leaves the template.
+    pub template: Option<&'t Template>,
+}
+
+/// The template is ignored when errors are compared.
+impl PartialEq for Error<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.span == other.span
+    }
+}
+
 #[derive(Debug, PartialEq)]
-pub enum Error<'t> {
+pub enum ErrorKind<'t> {
     Modifier(super::modifier::error::Error),
     UnknownVariable(&'t str),
     UnknownModifier(&'t str),
     UnknownTemplate,
+    NotIterable(super::value::Value),
+    CyclicTemplate(String),
+    Write(WriteError),
+}
+
+impl<'t> Error<'t> {
+    pub fn new(kind: ErrorKind<'t>, span: Option<&Substr>) -> Self {
+        Self {
+            kind,
+            span: span.cloned(),
+            template: None,
+        }
+    }
+
+    /// Sets the span unless the error already knows a more precise one.
+    pub fn or_span(mut self, span: Option<&Substr>) -> Self {
+        if self.span.is_none() {
+            self.span = span.cloned();
+        }
+        self
+    }
+
+    /// Attributes the error to `template` unless a template rendered by it already claimed it.
+    pub fn or_template(mut self, template: &'t Template) -> Self {
+        self.template.get_or_insert(template);
+        self
+    }
+}
+
+impl<'t> From<ErrorKind<'t>> for Error<'t> {
+    fn from(kind: ErrorKind<'t>) -> Self {
+        Self::new(kind, None)
+    }
+}
+
+impl<'t> From<std::fmt::Error> for Error<'t> {
+    fn from(_: std::fmt::Error) -> Self {
+        ErrorKind::Write(WriteError::Fmt).into()
+    }
 }
 
 impl<'t> std::error::Error for Error<'t> {}
 
 impl<'t> Display for Error<'t> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+impl<'t> Display for ErrorKind<'t> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Modifier(e) => e.fmt(f),
+            Self::UnknownVariable(var_name) => write!(f, "unknown variable {}", var_name),
+            Self::UnknownModifier(modifier_name) => write!(f, "unknown modifier {}", modifier_name),
+            Self::UnknownTemplate => write!(f, "unknown template"),
+            Self::NotIterable(value) => write!(f, "can not iterate over {}", value),
+            Self::CyclicTemplate(name) => write!(f, "template {} references itself", name),
+            Self::Write(e) => write!(f, "can not write output: {}", e),
+        }
+    }
+}
+
+/// The output a template is rendered into could not be written.
+#[derive(Debug)]
+pub enum WriteError {
+    Fmt,
+    Io(std::io::Error),
+}
+
+impl PartialEq for WriteError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Fmt, Self::Fmt) => true,
+            (Self::Io(s), Self::Io(o)) => s.kind() == o.kind(),
+            _ => false,
+        }
+    }
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fmt => write!(f, "formatter error"),
+            Self::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+/// An owned render error that does not borrow from the template.
+///
+/// This is the error returned by [`crate::MiniTemplate`]. It is `Send`, `Sync` and `'static`.
+/// Its `Display` implementation prints the offending line with a caret below the error.
+#[derive(Debug, PartialEq)]
+pub struct RenderError {
+    /// Name of the template the error occurred in. `None` if the template was added without a
+    /// name or the rendered template does not exist.
+    pub template: Option<String>,
+    /// Position inside the template source
+    pub position: Option<Box<Position>>,
+    pub kind: RenderErrorKind,
+}
+
+/// Position of an error inside the template source.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Position {
+    /// Line starting at 1
+    pub line: usize,
+    /// Column in characters starting at 1
+    pub column: usize,
+    /// Number of characters belonging to the error. Never reaches past the end of the line.
+    pub length: usize,
+    /// The full line containing the error
+    pub source_line: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RenderErrorKind {
+    Modifier(super::modifier::error::Error),
+    UnknownVariable(String),
+    UnknownModifier(String),
+    UnknownTemplate,
+    NotIterable(super::value::Value),
+    CyclicTemplate(String),
+    Write(WriteError),
+    /// The data passed to [`crate::MiniTemplate::render`] could not be serialized
+    Serialize(String),
+}
+
+impl RenderError {
+    /// Creates an owned error. `template` is the name of the template the error occurred in.
+    pub(crate) fn new(error: Error<'_>, template: Option<String>) -> Self {
+        let position = error
+            .span
+            .map(|span| Box::new(Position::new(span.source(), span.start(), &span)));
+        Self {
+            template,
+            position,
+            kind: error.kind.into(),
+        }
+    }
+}
+
+impl Position {
+    /// `span` is the part of `source` starting at `offset` the error belongs to.
+    pub(crate) fn new(source: &str, offset: usize, span: &str) -> Self {
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |i| offset + i);
+        let source_line = source[line_start..line_end].trim_end_matches('\r');
+        let column = source[line_start..offset].chars().count() + 1;
+        let length = span
+            .chars()
+            .take_while(|c| *c != '\n' && *c != '\r')
+            .count()
+            .max(1);
+        Self {
+            line: source[..offset].matches('\n').count() + 1,
+            column,
+            length,
+            source_line: source_line.to_owned(),
+        }
+    }
+
+    /// Writes the source line with a caret below the error.
+    pub(crate) fn write_snippet(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        // Keep tabs so the caret lines up with the source line
+        let indent = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        writeln!(f, "{} | {}", line_number, self.source_line)?;
+        write!(f, "{} | {}{}", gutter, indent, "^".repeat(self.length))
+    }
+}
+
+impl<'t> From<ErrorKind<'t>> for RenderErrorKind {
+    fn from(error: ErrorKind<'t>) -> Self {
+        match error {
+            ErrorKind::Modifier(e) => Self::Modifier(e),
+            ErrorKind::UnknownVariable(s) => Self::UnknownVariable(s.to_owned()),
+            ErrorKind::UnknownModifier(s) => Self::UnknownModifier(s.to_owned()),
+            ErrorKind::UnknownTemplate => Self::UnknownTemplate,
+            ErrorKind::NotIterable(v) => Self::NotIterable(v),
+            ErrorKind::CyclicTemplate(s) => Self::CyclicTemplate(s),
+            ErrorKind::Write(e) => Self::Write(e),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            RenderErrorKind::Modifier(e) => Some(e),
+            RenderErrorKind::Write(WriteError::Io(e)) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.template, &self.position) {
+            (None, _) => write!(f, "{}", self.kind),
+            (Some(template), None) => write!(f, "{} (template {})", self.kind, template),
+            (Some(template), Some(position)) => {
+                writeln!(
+                    f,
+                    "{} (template {}, line {}, column {})",
+                    self.kind, template, position.line, position.column
+                )?;
+                position.write_snippet(f)
+            }
+        }
+    }
+}
+
+impl Display for RenderErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Modifier(e) => e.fmt(f),
             Self::UnknownVariable(var_name) => write!(f, "unknown variable {}", var_name),
             Self::UnknownModifier(modifier_name) => write!(f, "unknown modifier {}", modifier_name),
             Self::UnknownTemplate => write!(f, "unknown template"),
+            Self::NotIterable(value) => write!(f, "can not iterate over {}", value),
+            Self::CyclicTemplate(name) => write!(f, "template {} references itself", name),
+            Self::Write(e) => write!(f, "can not write output: {}", e),
+            Self::Serialize(e) => write!(f, "can not serialize data: {}", e),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::template::Substr;
+
+    use super::{Error, ErrorKind, Position, RenderError, RenderErrorKind};
+
+    #[test]
+    fn render_error_is_send_sync_static() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<RenderError>();
+    }
+
+    #[test]
+    fn position_of_span() {
+        let source: Arc<str> = Arc::from("Hello\n  {foo} bar");
+        let span = Substr::new(&source, 9..12);
+        let error = RenderError::new(
+            Error::new(ErrorKind::UnknownVariable(&span), Some(&span)),
+            Some("tpl".to_owned()),
+        );
+        assert_eq!(
+            error,
+            RenderError {
+                template: Some("tpl".to_owned()),
+                position: Some(Box::new(Position {
+                    line: 2,
+                    column: 4,
+                    length: 3,
+                    source_line: "  {foo} bar".to_owned()
+                })),
+                kind: RenderErrorKind::UnknownVariable("foo".to_owned())
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "unknown variable foo (template tpl, line 2, column 4)\n2 |   {foo} bar\n  |    ^^^"
+        );
+    }
+
+    #[test]
+    fn error_without_span() {
+        let error = RenderError::new(
+            ErrorKind::UnknownVariable("foo").into(),
+            Some("tpl".to_owned()),
+        );
+        assert_eq!(error.position, None);
+        assert_eq!(error.to_string(), "unknown variable foo (template tpl)");
+    }
+
+    #[test]
+    fn multi_line_span() {
+        let source: Arc<str> = Arc::from("\tä{if a}\r\nb{endif}");
+        let error = RenderError::new(
+            Error::new(ErrorKind::UnknownTemplate, Some(&Substr::new(&source, 3..source.len()))),
+            Some("tpl".to_owned()),
+        );
+        assert_eq!(
+            error.position,
+            Some(Box::new(Position {
+                line: 1,
+                column: 3,
+                length: 6,
+                source_line: "\tä{if a}".to_owned()
+            }))
+        );
+        assert_eq!(
+            error.to_string(),
+            "unknown template (template tpl, line 1, column 3)\n1 | \tä{if a}\n  | \t ^^^^^^"
+        );
+    }
+
+    #[test]
+    fn or_span_keeps_precise_span() {
+        let source: Arc<str> = Arc::from("{foo|bar}");
+        let tag = Substr::new(&source, 0..source.len());
+        let error = Error::new(ErrorKind::UnknownModifier("bar"), Some(&tag.substr(5..8)));
+        assert_eq!(error.or_span(Some(&tag)).span.as_deref(), Some("bar"));
+        let error: Error = ErrorKind::UnknownTemplate.into();
+        assert_eq!(error.or_span(Some(&tag)).span.as_deref(), Some("{foo|bar}"));
+    }
+}
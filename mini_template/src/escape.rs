@@ -0,0 +1,78 @@
+use std::fmt::Write;
+
+/// Escaping applied to every printed value like `{name}`.
+///
+/// Literal template text is never escaped. A value can bypass escaping by ending its modifier
+/// chain with `raw`, e.g. `{trusted_html|raw}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Escape {
+    /// Values are printed as they are
+    #[default]
+    None,
+    /// Escapes `&`, `<`, `>`, `"` and `'` for use in HTML text and attributes
+    Html,
+    /// Escapes `&`, `<`, `>`, `"` and `'` using the predefined XML entities
+    Xml,
+}
+
+impl Escape {
+    /// Appends `input` to `buf` with all special characters replaced.
+    pub fn escape_into(&self, input: &str, buf: &mut dyn Write) -> std::fmt::Result {
+        let apos = match self {
+            Self::None => return buf.write_str(input),
+            Self::Html => "&#39;",
+            Self::Xml => "&apos;",
+        };
+
+        let mut last = 0;
+        for (i, c) in input.char_indices() {
+            let entity = match c {
+                '&' => "&amp;",
+                '<' => "&lt;",
+                '>' => "&gt;",
+                '"' => "&quot;",
+                '\'' => apos,
+                _ => continue,
+            };
+            buf.write_str(&input[last..i])?;
+            buf.write_str(entity)?;
+            last = i + 1;
+        }
+        buf.write_str(&input[last..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Escape;
+
+    fn escape(escape: Escape, input: &str) -> String {
+        let mut buf = String::new();
+        escape.escape_into(input, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn none() {
+        assert_eq!(
+            escape(Escape::None, "<a href='x'>&</a>"),
+            "<a href='x'>&</a>"
+        );
+    }
+
+    #[test]
+    fn html() {
+        assert_eq!(
+            escape(Escape::Html, r#"<script>alert("x" + 'y' & 1)</script>"#),
+            "&lt;script&gt;alert(&quot;x&quot; + &#39;y&#39; &amp; 1)&lt;/script&gt;"
+        );
+    }
+
+    #[test]
+    fn xml() {
+        assert_eq!(
+            escape(Escape::Xml, "<a b='c'>ä&ö</a>"),
+            "&lt;a b=&apos;c&apos;&gt;ä&amp;ö&lt;/a&gt;"
+        );
+    }
+}
@@ -1,33 +1,72 @@
-//#![deny(clippy::undocumented_unsafe_blocks)]
+#![forbid(unsafe_code)]
 
+pub mod delimiters;
 mod error;
+pub mod escape;
 pub mod macros;
 pub mod modifier;
 mod parser;
 mod renderer;
 mod template;
+pub mod undefined;
 pub mod value;
-mod variable_container;
+pub mod variable_container;
 
+// Lets code generated by `mini_template_macro` use the same paths inside this crate
+extern crate self as mini_template;
 #[macro_use]
 extern crate pest_derive;
 #[macro_use]
 extern crate log;
 
+use delimiters::Delimiters;
+use escape::Escape;
 use modifier::Modifier;
-use parser::{parse, ParseError};
-use renderer::RenderContext;
-use std::{collections::HashMap, hash::Hash};
+use parser::{parse, ParseOptions};
+use renderer::{IoWriter, RenderContext, TemplateRegistry};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, PoisonError, RwLock},
+};
 use template::{Render, Template};
-use variable_container::VariableContainer;
+use undefined::Undefined;
+use value::Value;
+use variable_container::{IntoVariableContainer, VariableContainer};
+
+pub use error::{Position, RenderError, RenderErrorKind, WriteError};
+pub use parser::{Diagnostic, ParseError};
 
 /// A Storage for Templates
 ///
-/// A MiniTemplate instance is used to parse, save and render templates.
-#[derive(Default)]
+/// A MiniTemplate instance is used to parse, save and render templates. `{include}` and
+/// `{extends}` refer to templates added with [`MiniTemplate::add_named_template`] by their name,
+/// like `{include "footer"}`.
 pub struct MiniTemplate<K: Eq + Hash> {
     modifier: HashMap<&'static str, &'static Modifier>,
-    template: HashMap<K, Template>,
+    /// Slots of the templates in `templates`
+    keys: HashMap<K, usize>,
+    templates: TemplateRegistry,
+    escape: Escape,
+    undefined: Undefined,
+    parse_options: ParseOptions,
+    /// Copied on write, so renders keep the globals they started with
+    globals: RwLock<Arc<HashMap<String, Value>>>,
+}
+
+// Implemented by hand because the derive would require `K: Default`
+impl<K: Eq + Hash> Default for MiniTemplate<K> {
+    fn default() -> Self {
+        MiniTemplate {
+            modifier: HashMap::new(),
+            keys: HashMap::new(),
+            templates: TemplateRegistry::default(),
+            escape: Escape::None,
+            undefined: Undefined::Error,
+            parse_options: ParseOptions::default(),
+            globals: RwLock::default(),
+        }
+    }
 }
 
 impl<K: Eq + Hash> MiniTemplate<K> {
@@ -35,10 +74,7 @@ impl<K: Eq + Hash> MiniTemplate<K> {
     /// Use [`MiniTemplate::default`] instead.
     #[deprecated]
     pub fn new() -> Self {
-        MiniTemplate {
-            modifier: HashMap::new(),
-            template: HashMap::new(),
-        }
+        Self::default()
     }
 
     /// Adds the following modifiers:
@@ -68,31 +104,846 @@ impl<K: Eq + Hash> MiniTemplate<K> {
     ///
     /// You can implement modifiers by hand. But that will result quite complex setup code.
     /// Preferably you should take a look at the [`mini_template::modifier::create_modifier`] macro.
+    ///
+    /// # Panics
+    /// Panics if `key` is the name of a built-in modifier: `raw`, `default`, `is_defined` or
+    /// `is_null`.
     pub fn add_modifier(&mut self, key: &'static str, modifier: &'static Modifier) {
+        assert!(
+            !template::BUILTIN_MODIFIERS.contains(&key),
+            "the {} modifier is built in and can not be replaced",
+            key
+        );
         self.modifier.insert(key, modifier);
     }
 
+    /// Sets the escaping applied to printed values in all templates.
+    ///
+    /// Values ending with the `raw` modifier are printed without escaping.
+    pub fn set_escape(&mut self, escape: Escape) {
+        self.escape = escape;
+    }
+
+    /// Sets how variables missing from the rendered data and unknown modifiers are handled in
+    /// all templates.
+    ///
+    /// By default rendering fails.
+    pub fn set_undefined(&mut self, undefined: Undefined) {
+        self.undefined = undefined;
+    }
+
+    /// Removes the first line break after tags like `{foreach}` or `{endforeach}`. Disabled by
+    /// default.
+    ///
+    /// This applies to every tag that does not print a value, including assignments, comments
+    /// and `{include}`. The first line break after `{if}`, `{elseif}`, `{else}`, `{endif}`,
+    /// `{while}` and `{endwhile}` is always removed. Tags printing a value like `{name}` are not
+    /// affected. Only applies to templates added afterwards.
+    pub fn set_trim_blocks(&mut self, trim_blocks: bool) {
+        self.parse_options.trim_blocks = trim_blocks;
+    }
+
+    /// Removes spaces and tabs between the start of a line and tags like `{if}` or `{endforeach}`.
+    /// Disabled by default.
+    ///
+    /// Tags printing a value like `{name}` are not affected. Only applies to templates added
+    /// afterwards.
+    pub fn set_lstrip_blocks(&mut self, lstrip_blocks: bool) {
+        self.parse_options.lstrip_blocks = lstrip_blocks;
+    }
+
+    /// Sets the strings opening and closing tags, `{` and `}` by default.
+    ///
+    /// Only applies to templates added afterwards.
+    pub fn set_delimiters(&mut self, delimiters: Delimiters) {
+        self.parse_options.delimiters = delimiters;
+    }
+
+    /// Sets a variable visible to all templates.
+    ///
+    /// Variables passed to `render` and assigned by the template take precedence over globals.
+    /// Globals can be changed while other threads render. Renders that already started keep
+    /// using the previous globals.
+    pub fn set_global(&self, key: String, value: Value) {
+        let mut globals = self.globals.write().unwrap_or_else(PoisonError::into_inner);
+        Arc::make_mut(&mut globals).insert(key, value);
+    }
+
+    /// Removes a global variable and returns its value.
+    pub fn remove_global(&self, key: &str) -> Option<Value> {
+        let mut globals = self.globals.write().unwrap_or_else(PoisonError::into_inner);
+        Arc::make_mut(&mut globals).remove(key)
+    }
+
+    /// Replaces all global variables.
+    pub fn set_globals(&self, globals: HashMap<String, Value>) {
+        *self.globals.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(globals);
+    }
+
+    /// Returns the current global variables.
+    pub fn globals(&self) -> Arc<HashMap<String, Value>> {
+        self.globals
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
     /// Register a new Template for a give key
+    /// # Error
+    /// Returns a [`ParseError`] listing the errors found in the template.
     pub fn add_template(&mut self, key: K, tpl: String) -> Result<Option<Template>, ParseError> {
-        let tpl = parse(tpl)?;
-        Ok(self.template.insert(key, tpl))
+        let delimiters = self.parse_options.delimiters.clone();
+        self.insert_template(None, key, tpl, delimiters)
+    }
+
+    /// Register a new Template for a given key and name.
+    ///
+    /// The name refers to the template in errors and in `{include}` and `{extends}` tags, like
+    /// `{include "footer"}`. Templates added without a name can not be included.
+    /// # Error
+    /// Returns a [`ParseError`] listing the errors found in the template.
+    pub fn add_named_template(
+        &mut self,
+        name: String,
+        key: K,
+        tpl: String,
+    ) -> Result<Option<Template>, ParseError> {
+        let delimiters = self.parse_options.delimiters.clone();
+        self.insert_template(Some(name), key, tpl, delimiters)
+    }
+
+    /// Register a new Template using other tag delimiters than set by
+    /// [`MiniTemplate::set_delimiters`].
+    /// # Error
+    /// Returns a [`ParseError`] listing the errors found in the template.
+    pub fn add_template_with_delimiters(
+        &mut self,
+        key: K,
+        tpl: String,
+        delimiters: Delimiters,
+    ) -> Result<Option<Template>, ParseError> {
+        self.insert_template(None, key, tpl, delimiters)
+    }
+
+    fn insert_template(
+        &mut self,
+        name: Option<String>,
+        key: K,
+        tpl: String,
+        delimiters: Delimiters,
+    ) -> Result<Option<Template>, ParseError> {
+        let options = ParseOptions {
+            delimiters,
+            ..self.parse_options.clone()
+        };
+        let mut tpl = parse(tpl, options).map_err(|e| match &name {
+            Some(name) => e.with_template(name.clone()),
+            None => e,
+        })?;
+        tpl.name = name;
+        match self.keys.get(&key) {
+            Some(&slot) => Ok(Some(self.templates.replace(slot, tpl))),
+            None => {
+                let slot = self.templates.push(tpl);
+                self.keys.insert(key, slot);
+                Ok(None)
+            }
+        }
     }
 
     /// Render the template for a given key.
     /// # Error
-    /// This function will return the following errors:
+    /// This function will return a [`RenderError`] of the following kinds:
     /// * Modifier: An unhandled error occurred inside a modifier
     /// * UnknownTemplate: There is no template with the given key registered
     /// * UnknownModifier: The template contains a unknown modifier
     /// * UnknownVariable: The template contains a unknown variable
-    pub fn render<VC: VariableContainer>(&self, key: &K, data: VC) -> error::Result<String> {
-        let tpl = match self.template.get(key) {
-            Some(t) => t,
-            None => return Err(error::Error::UnknownTemplate),
-        };
-        let mut context = RenderContext::new(&self.modifier, data);
+    /// * CyclicTemplate: A template includes or extends itself
+    /// * Serialize: `data` could not be serialized
+    ///
+    /// `data` is a [`VariableContainer`] like `HashMap<String, Value>`. With the `serde` feature
+    /// it can also be a reference to any struct or map implementing `serde::Serialize`.
+    pub fn render<M, D>(&self, key: &K, data: D) -> Result<String, RenderError>
+    where
+        D: IntoVariableContainer<M>,
+    {
         let mut buf = String::new();
-        tpl.render(&mut context, &mut buf)?;
+        self.render_to_fmt(key, data, &mut buf)?;
         Ok(buf)
     }
+
+    /// Render the template for a given key into a [`std::fmt::Write`].
+    ///
+    /// Output is written while rendering. If an error occurs, everything rendered up to that
+    /// point has already been written.
+    /// # Error
+    /// Returns the same errors as [`MiniTemplate::render`] and `Write` if the output could not
+    /// be written.
+    pub fn render_to_fmt<M, D, W>(&self, key: &K, data: D, out: &mut W) -> Result<(), RenderError>
+    where
+        D: IntoVariableContainer<M>,
+        W: std::fmt::Write,
+    {
+        let tpl = self.get_template(key)?;
+        let data = variable_container(tpl, data)?;
+        self.render_template(tpl, data, out)
+            .map_err(|e| self.owned_error(tpl, e))
+    }
+
+    /// Render the template for a given key into a [`std::io::Write`].
+    ///
+    /// Output is passed to the writer in small pieces while rendering. Wrap the writer in a
+    /// [`std::io::BufWriter`] if it is expensive to call. If an error occurs, everything rendered
+    /// up to that point has already been written.
+    /// # Error
+    /// Returns the same errors as [`MiniTemplate::render`] and `Write` if the writer fails.
+    pub fn render_to<M, D, W>(&self, key: &K, data: D, out: &mut W) -> Result<(), RenderError>
+    where
+        D: IntoVariableContainer<M>,
+        W: std::io::Write,
+    {
+        let tpl = self.get_template(key)?;
+        let data = variable_container(tpl, data)?;
+        let mut writer = IoWriter::new(out);
+        self.render_template(tpl, data, &mut writer)
+            .map_err(|e| self.owned_error(tpl, writer.take_error(e)))
+    }
+
+    fn get_template(&self, key: &K) -> Result<&Template, RenderError> {
+        let slot = self.keys.get(key).ok_or(RenderError {
+            template: None,
+            position: None,
+            kind: RenderErrorKind::UnknownTemplate,
+        })?;
+        Ok(self.templates.get(*slot))
+    }
+
+    fn render_template<'a, VC: VariableContainer>(
+        &'a self,
+        tpl: &'a Template,
+        data: VC,
+        out: &mut dyn std::fmt::Write,
+    ) -> error::Result<'a, ()> {
+        let mut context = RenderContext::new(&self.modifier, data);
+        context.templates = Some(&self.templates);
+        context.escape = self.escape;
+        context.undefined = self.undefined.clone();
+        context.variables.set_globals(self.globals());
+        tpl.render(&mut context, out)
+    }
+
+    /// Errors are attributed to the template they occurred in. This might be an included
+    /// template. Other errors are attributed to the rendered template `tpl`.
+    fn owned_error(&self, tpl: &Template, error: error::Error<'_>) -> RenderError {
+        let name = error.template.unwrap_or(tpl).name.clone();
+        RenderError::new(error, name)
+    }
+}
+
+fn variable_container<M, D>(tpl: &Template, data: D) -> Result<D::Container, RenderError>
+where
+    D: IntoVariableContainer<M>,
+{
+    data.into_variable_container().map_err(|kind| RenderError {
+        template: tpl.name.clone(),
+        position: None,
+        kind,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        delimiters::Delimiters, value::Value, MiniTemplate, Position, RenderError, RenderErrorKind,
+        WriteError,
+    };
+
+    /// Accepts `capacity` bytes and fails afterwards
+    struct LimitedWriter {
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl std::io::Write for LimitedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.written.len() + buf.len() > self.capacity {
+                return Err(std::io::Error::new(std::io::ErrorKind::WriteZero, "full"));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn mini_template() -> MiniTemplate<&'static str> {
+        let mut mini_template = MiniTemplate::default();
+        mini_template
+            .add_named_template(
+                "tpl".to_owned(),
+                "tpl",
+                String::from("Hello {name}! {missing}"),
+            )
+            .unwrap();
+        mini_template
+            .add_named_template("ok".to_owned(), "ok", String::from("Hello {name}!"))
+            .unwrap();
+        mini_template
+    }
+
+    fn variables() -> HashMap<String, Value> {
+        HashMap::from_iter([("name".to_owned(), Value::String("World".to_owned()))])
+    }
+
+    #[test]
+    fn render_to_io() {
+        let mut out = Vec::new();
+        mini_template()
+            .render_to(&"ok", variables(), &mut out)
+            .unwrap();
+        assert_eq!(out, b"Hello World!");
+    }
+
+    #[test]
+    fn render_to_fmt() {
+        let mut out = String::from("> ");
+        mini_template()
+            .render_to_fmt(&"ok", variables(), &mut out)
+            .unwrap();
+        assert_eq!(out, "> Hello World!");
+    }
+
+    #[test]
+    fn render_to_stops_on_render_error() {
+        let mini_template = mini_template();
+        let mut out = Vec::new();
+        assert_eq!(
+            mini_template.render_to(&"tpl", variables(), &mut out),
+            Err(RenderError {
+                template: Some("tpl".to_owned()),
+                position: Some(Box::new(Position {
+                    line: 1,
+                    column: 16,
+                    length: 7,
+                    source_line: "Hello {name}! {missing}".to_owned()
+                })),
+                kind: RenderErrorKind::UnknownVariable("missing".to_owned())
+            })
+        );
+        assert_eq!(out, b"Hello World! ");
+    }
+
+    #[test]
+    fn render_to_io_error() {
+        let mut out = LimitedWriter {
+            written: Vec::new(),
+            capacity: 8,
+        };
+        let mini_template = mini_template();
+        let result = mini_template.render_to(&"ok", variables(), &mut out);
+        assert_eq!(
+            result,
+            Err(RenderError {
+                template: Some("ok".to_owned()),
+                position: Some(Box::new(Position {
+                    line: 1,
+                    column: 8,
+                    length: 4,
+                    source_line: "Hello {name}!".to_owned()
+                })),
+                kind: RenderErrorKind::Write(WriteError::Io(std::io::Error::new(
+                    std::io::ErrorKind::WriteZero,
+                    "full"
+                )))
+            })
+        );
+        assert_eq!(out.written, b"Hello ");
+    }
+
+    #[test]
+    fn error_outlives_mini_template() {
+        fn render() -> Result<String, RenderError> {
+            mini_template().render(&"tpl", variables())
+        }
+        let error = render().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown variable missing (template tpl, line 1, column 16)\n\
+            1 | Hello {name}! {missing}\n  |                ^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn unknown_template_error() {
+        assert_eq!(
+            mini_template().render(&"missing", variables()),
+            Err(RenderError {
+                template: None,
+                position: None,
+                kind: RenderErrorKind::UnknownTemplate
+            })
+        );
+    }
+
+    #[cfg(feature = "include")]
+    #[test]
+    fn error_in_included_template() {
+        let mut mini_template = mini_template();
+        mini_template
+            .add_named_template(
+                "outer".to_owned(),
+                "outer",
+                String::from("Outer\n{include \"inner\"}"),
+            )
+            .unwrap();
+        mini_template
+            .add_named_template(
+                "inner".to_owned(),
+                "inner",
+                String::from("Inner\n\n  {name|unknown}"),
+            )
+            .unwrap();
+        assert_eq!(
+            mini_template.render(&"outer", variables()),
+            Err(RenderError {
+                template: Some("inner".to_owned()),
+                position: Some(Box::new(Position {
+                    line: 3,
+                    column: 9,
+                    length: 7,
+                    source_line: "  {name|unknown}".to_owned()
+                })),
+                kind: RenderErrorKind::UnknownModifier("unknown".to_owned())
+            })
+        );
+    }
+
+    #[cfg(feature = "inheritance")]
+    #[test]
+    fn error_in_overriding_block() {
+        let mut mini_template = mini_template();
+        mini_template
+            .add_named_template(
+                "base".to_owned(),
+                "base",
+                String::from("{block content}{endblock}"),
+            )
+            .unwrap();
+        mini_template
+            .add_named_template(
+                "child".to_owned(),
+                "child",
+                String::from("{extends \"base\"}\n{block content}{missing}{endblock}"),
+            )
+            .unwrap();
+        assert_eq!(
+            mini_template.render(&"child", variables()),
+            Err(RenderError {
+                template: Some("child".to_owned()),
+                position: Some(Box::new(Position {
+                    line: 2,
+                    column: 17,
+                    length: 7,
+                    source_line: "{block content}{missing}{endblock}".to_owned()
+                })),
+                kind: RenderErrorKind::UnknownVariable("missing".to_owned())
+            })
+        );
+    }
+
+    #[cfg(feature = "include")]
+    #[test]
+    fn include_by_name() {
+        // Keys do not need to implement `Display`
+        #[derive(PartialEq, Eq, Hash)]
+        enum Page {
+            Header,
+            Home,
+            Footer,
+        }
+
+        let mut pages = MiniTemplate::default();
+        pages
+            .add_named_template("header".to_owned(), Page::Header, String::from("# {name}"))
+            .unwrap();
+        pages
+            .add_template(Page::Home, String::from("{include \"header\"}!"))
+            .unwrap();
+        pages
+            .add_template(Page::Footer, String::from("{include \"home\"}"))
+            .unwrap();
+        assert_eq!(pages.render(&Page::Home, variables()).unwrap(), "# World!");
+        let error = pages.render(&Page::Footer, variables()).unwrap_err();
+        assert_eq!(error.template, None);
+        assert_eq!(error.kind, RenderErrorKind::UnknownTemplate);
+
+        let mut numbered = MiniTemplate::<u64>::default();
+        numbered
+            .add_named_template("1".to_owned(), 1, String::from("one"))
+            .unwrap();
+        numbered
+            .add_template(2, String::from("{include 1} two"))
+            .unwrap();
+        assert_eq!(numbered.render(&2, variables()).unwrap(), "one two");
+    }
+
+    #[test]
+    fn replace_template() {
+        let mut mini_template = mini_template();
+        let old = mini_template.add_template("ok", String::from("Bye {name}!")).unwrap();
+        assert!(old.is_some());
+        assert_eq!(mini_template.render(&"ok", variables()).unwrap(), "Bye World!");
+    }
+
+    #[cfg(feature = "foreach")]
+    #[test]
+    fn position_of_statement() {
+        let mut mini_template = mini_template();
+        mini_template
+            .add_named_template(
+                "loop".to_owned(),
+                "loop",
+                String::from("List:\n{foreach i in name|upper}{i}{endforeach}"),
+            )
+            .unwrap();
+        mini_template.add_modifier("upper", &crate::modifier::upper);
+        let error = mini_template.render(&"loop", variables()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "can not iterate over WORLD (template loop, line 2, column 15)\n\
+            2 | {foreach i in name|upper}{i}{endforeach}\n  |               ^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn parse_error_contains_template_key() {
+        let mut mini_template = MiniTemplate::default();
+        let error = mini_template
+            .add_named_template("broken".to_owned(), "broken", String::from("{name|}"))
+            .unwrap_err();
+        assert_eq!(error.template.as_deref(), Some("broken"));
+        assert_eq!(
+            error.to_string(),
+            "expected identifier, found `}` (template broken, line 1, column 7)\n\
+            1 | {name|}\n  |       ^"
+        );
+    }
+
+    fn render_str(mut mini_template: MiniTemplate<&'static str>, tpl: &str) -> String {
+        mini_template.add_template("tpl", tpl.to_owned()).unwrap();
+        let mut variables = variables();
+        variables.insert(
+            String::from("items"),
+            Value::Array(vec![Value::String("a".to_owned()), Value::String("b".to_owned())]),
+        );
+        mini_template.render(&"tpl", variables).unwrap()
+    }
+
+    #[test]
+    #[should_panic(expected = "the default modifier is built in")]
+    fn builtin_modifier_can_not_be_replaced() {
+        let mut mini_template = MiniTemplate::<&str>::default();
+        mini_template.add_modifier("default", &crate::modifier::upper);
+    }
+
+    #[cfg(all(feature = "foreach", feature = "include", feature = "assign"))]
+    #[test]
+    fn in_and_with_are_variable_names() {
+        let mut mini_template = MiniTemplate::default();
+        mini_template
+            .add_named_template("row".to_owned(), "row", String::from("{with}"))
+            .unwrap();
+        let tpl = r#"{in = items}{foreach with in in}{include "row" with with = with}{endforeach}"#;
+        assert_eq!(render_str(mini_template, tpl), "ab");
+    }
+
+    #[test]
+    fn trim_markers() {
+        let render = |tpl| render_str(MiniTemplate::default(), tpl);
+        assert_eq!(render("Hello \n {- name -} \n!"), "HelloWorld!");
+        assert_eq!(render("Hello {name-}\n  !"), "Hello World!");
+        assert_eq!(render("a {-* comment *-}\n b"), "ab");
+        assert_eq!(render("{-1} {- name}"), "-1World");
+    }
+
+    #[cfg(feature = "conditional")]
+    #[test]
+    fn trim_markers_on_blocks() {
+        let render = |tpl| render_str(MiniTemplate::default(), tpl);
+        assert_eq!(render("<{if true -}\n  yes\n{- else}no{endif}>"), "<yes>");
+        assert_eq!(render("<{if false}yes{else -}  no  {-endif}>"), "<no>");
+    }
+
+    #[cfg(all(feature = "conditional", feature = "foreach"))]
+    #[test]
+    fn trim_blocks() {
+        let tpl = "{if true}\n  yes\n{endif}\n{foreach i in items}\n{i}\n{endforeach}\n{name}";
+        assert_eq!(render_str(MiniTemplate::default(), tpl), "  yes\n\na\n\nb\n\nWorld");
+
+        let mut mini_template = MiniTemplate::default();
+        mini_template.set_trim_blocks(true);
+        assert_eq!(render_str(mini_template, tpl), "  yes\na\nb\nWorld");
+    }
+
+    #[cfg(feature = "assign")]
+    #[test]
+    fn trim_blocks_after_assign_and_comment() {
+        let tpl = "{a = 1}\n{* note *}\n{a}\n";
+        assert_eq!(render_str(MiniTemplate::default(), tpl), "\n\n1\n");
+
+        let mut mini_template = MiniTemplate::default();
+        mini_template.set_trim_blocks(true);
+        assert_eq!(render_str(mini_template, tpl), "1\n");
+    }
+
+    #[cfg(feature = "foreach")]
+    #[test]
+    fn lstrip_blocks() {
+        let tpl = "<ul>\n  {foreach i in items}\n  <li>{i}</li>\n  {endforeach}\n</ul>\n  {name}";
+        let mut mini_template = MiniTemplate::default();
+        mini_template.set_trim_blocks(true);
+        mini_template.set_lstrip_blocks(true);
+        assert_eq!(
+            render_str(mini_template, tpl),
+            "<ul>\n  <li>a</li>\n  <li>b</li>\n</ul>\n  World"
+        );
+    }
+
+    #[cfg(feature = "conditional")]
+    #[test]
+    fn undefined_variables() {
+        let tpl = "{if user|is_defined}{user}{else}guest{endif} {title|default:name}{missing}";
+        let mut mini_template = MiniTemplate::default();
+        mini_template.add_template("tpl", tpl.to_owned()).unwrap();
+        assert_eq!(
+            mini_template.render(&"tpl", variables()).unwrap_err().kind,
+            RenderErrorKind::UnknownVariable(String::from("missing"))
+        );
+
+        mini_template.set_undefined(crate::undefined::Undefined::Null);
+        assert_eq!(render_str(mini_template, tpl), "guest World");
+    }
+
+    #[test]
+    fn custom_delimiters() {
+        let mut mini_template = MiniTemplate::default();
+        mini_template.set_delimiters(Delimiters::new("{{", "}}"));
+        assert_eq!(
+            render_str(mini_template, r#"{"name": "{{name}}", "x": {{* {} *}}{{"}}"}}}"#),
+            r#"{"name": "World", "x": }}}"#
+        );
+    }
+
+    #[cfg(feature = "foreach")]
+    #[test]
+    fn custom_delimiters_on_blocks() {
+        let mut mini_template = MiniTemplate::default();
+        mini_template.set_delimiters(Delimiters::new("<%", "%>"));
+        mini_template.set_trim_blocks(true);
+        let tpl = "<%foreach i in items%>\n{ <%i-%> }\n<%endforeach%>\n<%-* done *%>";
+        assert_eq!(render_str(mini_template, tpl), "{ a}\n{ b}\n");
+    }
+
+    #[test]
+    fn literal_block() {
+        let tpl = "{literal}\nf({name}) {\\n}\n{/literal}{name}";
+        assert_eq!(render_str(MiniTemplate::default(), tpl), "\nf({name}) {\\n}\nWorld");
+
+        let mut mini_template = MiniTemplate::default();
+        mini_template.set_delimiters(Delimiters::new("{{", "}}"));
+        let tpl = "{{literal}}{{name}} \\{{/literal}}{{name}}";
+        assert_eq!(render_str(mini_template, tpl), "{{name}} \\World");
+    }
+
+    #[test]
+    fn template_with_delimiters() {
+        let mut mini_template = MiniTemplate::default();
+        mini_template
+            .add_template_with_delimiters(
+                "tpl",
+                String::from("{x} [[name]]"),
+                Delimiters::new("[[", "]]"),
+            )
+            .unwrap();
+        mini_template.add_template("default", String::from("{name}")).unwrap();
+        assert_eq!(mini_template.render(&"tpl", variables()).unwrap(), "{x} World");
+        assert_eq!(mini_template.render(&"default", variables()).unwrap(), "World");
+    }
+
+    /// Builds random templates from fragments written with both `{ }` and `<% %>` and checks
+    /// that the translation of the custom delimiters parses and renders like the plain grammar.
+    #[cfg(all(feature = "conditional", feature = "foreach", feature = "assign"))]
+    #[test]
+    fn delimiter_translation_matches_grammar() {
+        const FRAGMENTS: &[(&str, &str)] = &[
+            ("Hello ", "Hello "),
+            ("\n", "\n"),
+            ("  ", "  "),
+            ("-*%\"|", "-*%\"|"),
+            ("{name}", "<%name%>"),
+            ("{- name -}", "<%- name -%>"),
+            ("{name|upper}", "<%name|upper%>"),
+            ("{x + 1}", "<%x + 1%>"),
+            (r#"{"%>\"}"|upper}"#, r#"<%"%>\"}"|upper%>"#),
+            ("{* {x} *}", "<%* {x} *%>"),
+            ("{-* c *-}", "<%-* c *-%>"),
+            ("{x = 2}", "<%x = 2%>"),
+            (
+                "{if a}\n  {name}\n{- else -}\n{x}{endif}\n",
+                "<%if a%>\n  <%name%>\n<%- else -%>\n<%x%><%endif%>\n",
+            ),
+            (
+                "  {foreach i in items}\n {i}\n{endforeach}",
+                "  <%foreach i in items%>\n <%i%>\n<%endforeach%>",
+            ),
+            ("{endif}", "<%endif%>"),
+            ("{literal} raw {/literal}", "<%literal%> raw <%/literal%>"),
+            ("{", "<%"),
+        ];
+
+        let render = |tpl: String, delimiters: Delimiters| {
+            let mut mini_template = MiniTemplate::default();
+            mini_template.set_delimiters(delimiters);
+            mini_template.set_trim_blocks(true);
+            mini_template.set_lstrip_blocks(true);
+            mini_template.add_template("tpl", tpl).map_err(|_| None)?;
+            let data = HashMap::from_iter([
+                ("name".to_owned(), Value::String("World".to_owned())),
+                ("x".to_owned(), Value::Integer(1)),
+                ("a".to_owned(), Value::Bool(true)),
+                ("items".to_owned(), Value::Array(vec![Value::Integer(1), Value::Integer(2)])),
+            ]);
+            mini_template
+                .render(&"tpl", data)
+                .map_err(|e| Some(e.kind))
+        };
+
+        // Linear congruential generator, so failures can be reproduced
+        let mut state = 0x2545_f491_u64;
+        let mut next = |bound: usize| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) as usize % bound
+        };
+        for _ in 0..2000 {
+            let (mut plain, mut custom) = (String::new(), String::new());
+            for _ in 0..1 + next(8) {
+                let (p, c) = FRAGMENTS[next(FRAGMENTS.len())];
+                plain.push_str(p);
+                custom.push_str(c);
+            }
+            assert_eq!(
+                render(custom.clone(), Delimiters::new("<%", "%>")),
+                render(plain.clone(), Delimiters::default()),
+                "{:?} translated differently than {:?}",
+                custom,
+                plain
+            );
+        }
+    }
+
+    #[test]
+    fn parse_error_with_delimiters() {
+        let mut mini_template = MiniTemplate::default();
+        mini_template.set_delimiters(Delimiters::new("<%", "%>"));
+        let error = mini_template
+            .add_named_template(
+                "broken".to_owned(),
+                "broken",
+                String::from("{}\n<%* x *%><%name|%>"),
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected identifier, found `%>` (template broken, line 2, column 17)\n\
+            2 | <%* x *%><%name|%>\n  |                 ^"
+        );
+        let error = mini_template
+            .add_template("unclosed", String::from("<%* x *}"))
+            .unwrap_err();
+        assert!(error.to_string().starts_with("expected `*%>`, found end of template"));
+    }
+
+    #[test]
+    fn render_from_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<MiniTemplate<String>>();
+
+        let mini_template = std::sync::Arc::new(mini_template());
+        let handles = (0..4)
+            .map(|i| {
+                let mini_template = std::sync::Arc::clone(&mini_template);
+                std::thread::spawn(move || {
+                    let mut data = HashMap::new();
+                    data.insert(String::from("name"), Value::String(i.to_string()));
+                    data.insert(String::from("missing"), Value::Bool(true));
+                    mini_template.render(&"tpl", data).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join().unwrap(), format!("Hello {i}! true"));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn render_serialize() {
+        #[derive(serde::Serialize)]
+        struct Order {
+            customer: &'static str,
+            items: Vec<(&'static str, u32)>,
+            note: Option<&'static str>,
+        }
+
+        let mut mini_template = MiniTemplate::default();
+        mini_template
+            .add_template(
+                0,
+                "{customer}: {items[0][0]} x{items[0][1]}{note|default:\"\"}".to_owned(),
+            )
+            .unwrap();
+        let order = Order {
+            customer: "Ferris",
+            items: vec![("crab", 2)],
+            note: None,
+        };
+        assert_eq!(mini_template.render(&0, &order).unwrap(), "Ferris: crab x2");
+
+        let error = mini_template.render(&0, &[1, 2]).unwrap_err();
+        assert_eq!(
+            error.kind,
+            RenderErrorKind::Serialize("data must be a struct or map".to_owned())
+        );
+    }
+
+    #[test]
+    fn globals() {
+        let mut mini_template = MiniTemplate::default();
+        mini_template
+            .add_template(0, "{company} {year} {name}".to_owned())
+            .unwrap();
+        mini_template.set_global("company".to_owned(), Value::String("ACME".to_owned()));
+        mini_template.set_global("name".to_owned(), Value::String("global".to_owned()));
+        mini_template.set_global("year".to_owned(), Value::Integer(2025));
+        assert_eq!(mini_template.render(&0, variables()).unwrap(), "ACME 2025 World");
+
+        let mini_template = std::sync::Arc::new(mini_template);
+        mini_template.set_global("year".to_owned(), Value::Integer(2026));
+        assert_eq!(mini_template.remove_global("company"), Some(Value::from("ACME".to_owned())));
+        assert_eq!(mini_template.globals().len(), 2);
+        let error = mini_template.render(&0, variables()).unwrap_err();
+        assert_eq!(error.kind, RenderErrorKind::UnknownVariable("company".to_owned()));
+
+        let mut globals = HashMap::new();
+        globals.insert("company".to_owned(), Value::String("Initech".to_owned()));
+        globals.insert("year".to_owned(), Value::Integer(1999));
+        mini_template.set_globals(globals);
+        assert_eq!(mini_template.render(&0, variables()).unwrap(), "Initech 1999 World");
+    }
 }
@@ -6,7 +6,7 @@ pub use mini_template_macro::create_modifier;
 ///
 /// Possible parameter types are:
 ///
-/// &str, String, bool, f64, isize, i32, usize, u32
+/// &str, String, bool, f64, isize, i32, usize, u32, i64, u64, i128
 ///
 /// # Example
 /// ```
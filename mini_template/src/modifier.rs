@@ -9,7 +9,7 @@ use {
     },
 };
 
-use core::ops::{Add, Mul, Div, Sub};
+use core::ops::{Add, Mul, Div, Rem, Sub};
 use super::value::Value;
 pub use error::*;
 use crate::fn_as_modifier;
@@ -17,7 +17,7 @@ use crate::fn_as_modifier;
 #[cfg(feature = "regex")]
 static REGEX_CACHE: OnceCell<RwLock<HashMap<u64, Regex>>> = OnceCell::new();
 
-pub type Modifier = dyn Fn(&Value, Vec<&Value>) -> Result<Value>;
+pub type Modifier = dyn Fn(&Value, Vec<&Value>) -> Result<Value> + Send + Sync;
 
 #[mini_template_macro::create_modifier]
 fn slice_modifier(input: String, start: usize, length: usize) -> String {
@@ -60,13 +60,64 @@ fn_as_modifier!(fn upper(input: &str) -> String => str::to_uppercase);
 
 fn_as_modifier!(fn lower(input: &str) -> String => str::to_lowercase);
 
-fn_as_modifier!(fn add(a: f64, b: f64) -> f64 => f64::add);
+pub fn add(value: &Value, args: Vec<&Value>) -> Result<Value> {
+    arithmetic(value, args, |a, b| a.checked_add(b).map(Value::Integer), f64::add)
+}
+
+pub fn sub(value: &Value, args: Vec<&Value>) -> Result<Value> {
+    arithmetic(value, args, |a, b| a.checked_sub(b).map(Value::Integer), f64::sub)
+}
 
-fn_as_modifier!(fn sub(a: f64, b: f64) -> f64 => f64::sub);
+pub fn mul(value: &Value, args: Vec<&Value>) -> Result<Value> {
+    arithmetic(value, args, |a, b| a.checked_mul(b).map(Value::Integer), f64::mul)
+}
+
+/// Integers that do not divide evenly result in a float.
+pub fn div(value: &Value, args: Vec<&Value>) -> Result<Value> {
+    arithmetic(
+        value,
+        args,
+        |a, b| match a.checked_rem(b)? {
+            0 => a.checked_div(b).map(Value::Integer),
+            _ => Some(Value::Number(a as f64 / b as f64)),
+        },
+        f64::div,
+    )
+}
 
-fn_as_modifier!(fn mul(a: f64, b: f64) -> f64 => f64::mul);
+/// Remainder of a division. Used by the `%` operator.
+pub fn rem(value: &Value, args: Vec<&Value>) -> Result<Value> {
+    arithmetic(value, args, |a, b| a.checked_rem(b).map(Value::Integer), f64::rem)
+}
 
-fn_as_modifier!(fn div(a: f64, b: f64) -> f64 => f64::div);
+/// Calculates with integers if both operands are integers and with floats otherwise.
+///
+/// `int` returns `None` if the result overflows or is undefined. Float results that are infinite
+/// or not a number, e.g. of a division by zero, are errors as well.
+fn arithmetic(
+    a: &Value,
+    args: Vec<&Value>,
+    int: fn(i128, i128) -> Option<Value>,
+    float: fn(f64, f64) -> f64,
+) -> Result<Value> {
+    let b = *args.first().ok_or(Error::MissingArgument { argument_name: "b" })?;
+    if let (Value::Integer(a), Value::Integer(b)) = (a, b) {
+        return int(*a, *b)
+            .ok_or_else(|| Error::Modifier(format!("can not calculate with {} and {}", a, b)));
+    }
+    let to_f64 = |value: &Value| {
+        f64::try_from(value).map_err(|type_error| Error::Type {
+            value: value.to_string(),
+            type_error,
+        })
+    };
+    let (a, b) = (to_f64(a)?, to_f64(b)?);
+    let result = float(a, b);
+    if !result.is_finite() {
+        return Err(Error::Modifier(format!("can not calculate with {} and {}", a, b)));
+    }
+    Ok(Value::Number(result))
+}
 
 fn_as_modifier!(fn repeat(input: &str, n: usize) -> String => str::repeat);
 
@@ -79,7 +130,6 @@ where
     let mut hasher = DefaultHasher::new();
     regex.hash(&mut hasher);
     let cache_key = hasher.finish();
-    drop(hasher);
 
     let cache = REGEX_CACHE.get_or_init(Default::default);
     let cache_r = cache.read().unwrap();
@@ -124,6 +174,8 @@ pub mod error {
         Modifier(String),
     }
 
+    impl std::error::Error for Error {}
+
     impl Display for Error {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             match self {
@@ -227,13 +279,51 @@ mod tests {
             Err(Error::Type {
                 type_error: TypeError {
                     expected_type: "usize",
-                    storage_type: "Number"
+                    storage_type: "Integer"
                 },
                 value: String::from("string")
             })
         );
     }
 
+    #[test]
+    fn integer_arithmetic() {
+        let big = Value::from(u64::MAX);
+        let two = Value::Integer(2);
+        assert_eq!(add(&big, vec![&two]), Ok(Value::Integer(u64::MAX as i128 + 2)));
+        assert_eq!(sub(&two, vec![&big]), Ok(Value::Integer(2 - u64::MAX as i128)));
+        assert_eq!(mul(&big, vec![&two]), Ok(Value::Integer(u64::MAX as i128 * 2)));
+        assert_eq!(div(&Value::Integer(8), vec![&two]), Ok(Value::Integer(4)));
+        assert_eq!(div(&Value::Integer(7), vec![&two]), Ok(Value::Number(3.5)));
+        assert_eq!(add(&Value::Number(0.5), vec![&two]), Ok(Value::Number(2.5)));
+    }
+
+    #[test]
+    fn integer_overflow() {
+        let max = Value::Integer(i128::MAX);
+        assert_eq!(
+            mul(&max, vec![&Value::Integer(2)]),
+            Err(Error::Modifier(format!("can not calculate with {} and 2", i128::MAX)))
+        );
+        assert_eq!(
+            div(&Value::Integer(1), vec![&Value::Integer(0)]),
+            Err(Error::Modifier(String::from("can not calculate with 1 and 0")))
+        );
+    }
+
+    #[test]
+    fn float_division_by_zero() {
+        let five = Value::Number(5.5);
+        let zero = Value::Integer(0);
+        let error = Err(Error::Modifier(String::from("can not calculate with 5.5 and 0")));
+        assert_eq!(div(&five, vec![&zero]), error);
+        assert_eq!(rem(&five, vec![&zero]), error);
+        assert_eq!(
+            mul(&Value::Number(f64::MAX), vec![&Value::Integer(2)]),
+            Err(Error::Modifier(format!("can not calculate with {} and 2", f64::MAX)))
+        );
+    }
+
     #[test]
     fn lower_modifier() {
         let input = Value::String(String::from("Hello World!"));
@@ -1,4 +1,10 @@
-use pest::{error::LineColLocation, iterators::Pair, Parser};
+use std::{fmt::Display, ops::Range, sync::Arc};
+
+use pest::{
+    error::{ErrorVariant, InputLocation},
+    iterators::Pair,
+    Parser,
+};
 
 #[cfg(feature = "condition")]
 use crate::template::condition::{
@@ -8,10 +14,21 @@ use crate::template::condition::{
 use crate::template::Assign;
 #[cfg(feature = "conditional")]
 use crate::template::Conditional;
+#[cfg(feature = "foreach")]
+use crate::template::Foreach;
+#[cfg(feature = "include")]
+use crate::template::Include;
+#[cfg(feature = "inheritance")]
+use crate::template::{Block, Extends, Parent};
 #[cfg(feature = "loop")]
 use crate::template::Loop;
 use crate::{
-    template::{CalculatedValue, Statement, StorageMethod},
+    delimiters::{Delimiters, Translation},
+    error::Position,
+    template::{
+        CalculatedValue, Operator, PathSegment, Span, Statement, StorageMethod, Substr,
+        VariablePath,
+    },
     value::Value,
     Template,
 };
@@ -21,95 +38,328 @@ use crate::{
 #[grammar = "template.pest"]
 pub struct TemplateParser;
 
-pub fn parse(input: String) -> Result<Template, ParseError> {
-    let mut compiled_template = Template {
-        tpl: Vec::new(),
-        tpl_str: input,
-    };
-    let template = match TemplateParser::parse(Rule::template, &compiled_template.tpl_str) {
-        Ok(t) => t,
-        Err(e) => match e.line_col {
-            LineColLocation::Pos(pos) => {
-                return Err(ParseError::Pos(pos));
+/// Tag delimiters and whitespace handling around tags that do not print a value, like `{if}`
+/// or `{endforeach}`.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Removes the first line break after a tag
+    pub trim_blocks: bool,
+    /// Removes spaces and tabs between the start of a line and a tag
+    pub lstrip_blocks: bool,
+    pub delimiters: Delimiters,
+}
+
+/// The template source and everything parse functions need to know about it.
+///
+/// Templates with other delimiters than `{` and `}` are translated before parsing. All offsets
+/// of parsed pairs refer to `input` and are mapped back to `text` with [`Source::offset`].
+struct Source {
+    text: Arc<str>,
+    input: Arc<str>,
+    translation: Option<Translation>,
+    options: ParseOptions,
+    /// Ranges of all tags printing a value like `{name}` in `input` in order of occurrence
+    value_tags: Vec<Range<usize>>,
+    /// Ends of all `{if}`, `{elseif}`, `{else}`, `{endif}`, `{while}` and `{endwhile}` tags in
+    /// order of occurrence. The first line break after them is removed even without
+    /// `trim_blocks`, like in earlier versions.
+    block_tags: Vec<usize>,
+}
+
+impl Source {
+    fn new(text: Arc<str>, options: ParseOptions) -> Self {
+        let delimiters = &options.delimiters;
+        let translation = (!delimiters.is_default()).then(|| Translation::new(&text, delimiters));
+        let input = match &translation {
+            Some(translation) => Arc::from(translation.input.as_str()),
+            None => Arc::clone(&text),
+        };
+        Self {
+            text,
+            input,
+            translation,
+            options,
+            value_tags: Vec::new(),
+            block_tags: Vec::new(),
+        }
+    }
+
+    /// Maps an offset in the parsed input to the template source.
+    fn offset(&self, offset: usize) -> usize {
+        match &self.translation {
+            Some(translation) => translation.source_offset(offset),
+            None => offset,
+        }
+    }
+
+    /// Returns the part of the template source parsed from `range` of the input.
+    fn substr(&self, range: Range<usize>) -> Substr {
+        Substr::new(&self.text, self.offset(range.start)..self.offset(range.end))
+    }
+
+    /// Returns the range of the text between `range.start` and `range.end` without the
+    /// whitespace removed by trim markers and options.
+    fn trim(&self, range: Range<usize>) -> Range<usize> {
+        let text = &self.input;
+        let Range { mut start, mut end } = range;
+
+        // A tag ends at `start`
+        if start > 0 {
+            if text[..start].ends_with("-}") {
+                start = end - text[start..end].trim_start().len();
+            } else if self.options.trim_blocks && !self.is_value_tag(start, |r| r.end)
+                || self.block_tags.binary_search(&start).is_ok()
+            {
+                let rest = &text[start..end];
+                let rest = rest
+                    .strip_prefix("\r\n")
+                    .or_else(|| rest.strip_prefix('\n'))
+                    .unwrap_or(rest);
+                start = end - rest.len();
             }
-            LineColLocation::Span(start, end) => {
-                return Err(ParseError::Span(start, end));
+        }
+
+        // A tag starts at `end`
+        if end < text.len() {
+            let rest = &text[end..];
+            if rest.starts_with("{-") && !rest[2..].starts_with(|c: char| c.is_ascii_digit()) {
+                end = start + text[start..end].trim_end().len();
+            } else if self.options.lstrip_blocks && !self.is_value_tag(end, |r| r.start) {
+                let stripped = start + text[start..end].trim_end_matches([' ', '\t']).len();
+                if stripped == 0 || text[..stripped].ends_with('\n') {
+                    end = stripped;
+                }
             }
-        },
+        }
+
+        start..end.max(start)
     }
-    .next()
-    .unwrap();
-    compiled_template.tpl = template
-        .into_inner()
+
+    /// `key` selects the start or end of the tags to compare `offset` with.
+    fn is_value_tag(&self, offset: usize, key: fn(&Range<usize>) -> usize) -> bool {
+        self.value_tags.binary_search_by_key(&offset, key).is_ok()
+    }
+}
+
+pub fn parse(input: String, options: ParseOptions) -> Result<Template, ParseError> {
+    let text: Arc<str> = Arc::from(input);
+    let mut source = Source::new(Arc::clone(&text), options);
+    let input = Arc::clone(&source.input);
+    let template = TemplateParser::parse(Rule::template, &input)
+        .map_err(|e| ParseError::from_pest(e, &source))?
         .next()
-        .unwrap()
+        .unwrap();
+    source.value_tags = template
+        .clone()
         .into_inner()
-        .filter_map(parse_template_content)
-        .collect::<Result<Vec<_>, _>>()?;
-    Ok(compiled_template)
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::calculated)
+        .map(|pair| pair.as_span().start()..pair.as_span().end())
+        .collect();
+    source.block_tags = template
+        .clone()
+        .into_inner()
+        .flatten()
+        .filter(|pair| {
+            matches!(
+                pair.as_rule(),
+                Rule::if_tag
+                    | Rule::elseif_tag
+                    | Rule::else_tag
+                    | Rule::endif_tag
+                    | Rule::while_tag
+                    | Rule::endwhile_tag
+            )
+        })
+        .map(|pair| pair.as_span().end())
+        .collect();
+    check_indices(&template, &source)?;
+    let tpl = parse_statements(template.into_inner().next().unwrap(), &source)?;
+    Ok(Template {
+        name: None,
+        tpl_str: text,
+        tpl,
+    })
+}
+
+/// Reports index segments like `[99999999999999999999999]` that do not fit into `usize`.
+fn check_indices(template: &Pair<Rule>, source: &Source) -> Result<(), ParseError> {
+    let mut errors = Errors::default();
+    for segment in template.clone().into_inner().flatten() {
+        if segment.as_rule() == Rule::index && segment.as_str().parse::<usize>().is_err() {
+            let message = format!("index {} is too large", segment.as_str());
+            errors.take::<()>(Err(ParseError::at(&segment, source, message)));
+        }
+    }
+    errors.finish(())
+}
+
+/// Rejects value tags like `{-a}` and `{-(a)}`, which could be meant as a trim marker or as a
+/// negation. Negative numbers like `{-1}` are not affected.
+fn check_trim_marker(tag: &Pair<Rule>, source: &Source) -> Result<(), ParseError> {
+    let value = tag.as_str().strip_prefix("{-").unwrap_or_default();
+    if !value.starts_with(|c: char| c.is_ascii_lowercase() || c == '(') {
+        return Ok(());
+    }
+    let delimiters = &source.options.delimiters;
+    let message = format!(
+        "`{open}-` is ambiguous, write `{open}- value{close}` to trim whitespace \
+        or `{open}(-value){close}` to negate",
+        open = delimiters.open(),
+        close = delimiters.close(),
+    );
+    Err(ParseError::at(tag, source, message))
+}
+
+/// Returns the part of the template source `pair` was parsed from.
+fn substr(pair: &Pair<Rule>, source: &Source) -> Substr {
+    let span = pair.as_span();
+    source.substr(span.start()..span.end())
+}
+
+/// Returns the content of a tag like `{if condition}`.
+#[cfg(any(
+    feature = "conditional",
+    feature = "loop",
+    feature = "foreach",
+    feature = "inheritance"
+))]
+fn tag_inner(tag: Pair<Rule>, rule: Rule) -> pest::iterators::Pairs<Rule> {
+    assert_eq!(tag.as_rule(), rule);
+    tag.into_inner()
+}
+
+/// Parses the statements of a `template_content` pair.
+/// Reports the errors of all statements instead of stopping at the first one.
+fn parse_statements(content: Pair<Rule>, source: &Source) -> Result<Vec<Statement>, ParseError> {
+    assert_eq!(content.as_rule(), Rule::template_content);
+    let mut errors = Errors::default();
+    let statements = content
+        .into_inner()
+        .filter_map(|item| parse_template_content(item, source))
+        .filter_map(|statement| errors.take(statement))
+        .collect();
+    errors.finish(statements)
 }
 
-fn parse_template_content(item: Pair<Rule>) -> Option<Result<Statement, ParseError>> {
+fn parse_template_content(
+    item: Pair<Rule>,
+    source: &Source,
+) -> Option<Result<Statement, ParseError>> {
     match item.as_rule() {
-        Rule::text => Some(Ok(Statement::Literal(item.as_str()))),
-        Rule::calculated => Some(Ok(parse_calculated(item))),
+        Rule::text => {
+            let span = item.as_span();
+            let range = source.trim(span.start()..span.end());
+            (!range.is_empty()).then(|| Ok(Statement::Literal(source.substr(range))))
+        }
+        Rule::literal_block => {
+            let content = item.into_inner().nth(1).unwrap();
+            let span = content.as_span();
+            let range = source.trim(span.start()..span.end());
+            (!range.is_empty()).then(|| Ok(Statement::Literal(source.substr(range))))
+        }
+        Rule::calculated => {
+            Some(check_trim_marker(&item, source).map(|()| parse_calculated(item, source)))
+        }
         #[cfg(feature = "conditional")]
-        Rule::conditional => Some(parse_conditional(item)),
+        Rule::conditional => Some(parse_conditional(item, source)),
         #[cfg(not(feature = "conditional"))]
-        Rule::conditional => Some(Err(ParseError::DisabledFeature(
-            UnsupportedFeature::Conditional,
+        Rule::conditional => Some(Err(ParseError::disabled_feature(
+            &item,
+            source,
+            "conditional",
         ))),
         #[cfg(feature = "assign")]
-        Rule::assign => Some(Ok(Statement::Assign(parse_assign(item)))),
+        Rule::assign => Some(Ok(Statement::Assign(parse_assign(item, source)))),
         #[cfg(not(feature = "assign"))]
-        Rule::assign => Some(Err(ParseError::DisabledFeature(UnsupportedFeature::Assign))),
+        Rule::assign => Some(Err(ParseError::disabled_feature(&item, source, "assign"))),
         #[cfg(feature = "loop")]
-        Rule::while_loop => match parse_loop(item) {
-            Ok(l) => Some(Ok(Statement::Loop(l))),
+        Rule::while_loop => match parse_loop(item, source) {
+            Ok(l) => Some(Ok(Statement::Loop(Box::new(l)))),
             Err(e) => Some(Err(e)),
         },
         #[cfg(not(feature = "loop"))]
-        Rule::while_loop => Some(Err(ParseError::DisabledFeature(UnsupportedFeature::Loop))),
-        Rule::EOI => None,
+        Rule::while_loop => Some(Err(ParseError::disabled_feature(&item, source, "loop"))),
+        #[cfg(feature = "foreach")]
+        Rule::foreach_loop => match parse_foreach(item, source) {
+            Ok(f) => Some(Ok(Statement::Foreach(f))),
+            Err(e) => Some(Err(e)),
+        },
+        #[cfg(not(feature = "foreach"))]
+        Rule::foreach_loop => Some(Err(ParseError::disabled_feature(
+            &item,
+            source,
+            "foreach",
+        ))),
+        #[cfg(feature = "include")]
+        Rule::include => Some(Ok(Statement::Include(parse_include(item, source)))),
+        #[cfg(not(feature = "include"))]
+        Rule::include => Some(Err(ParseError::disabled_feature(&item, source, "include"))),
+        #[cfg(feature = "inheritance")]
+        Rule::extends => Some(Ok(Statement::Extends(parse_extends(item, source)))),
+        #[cfg(feature = "inheritance")]
+        Rule::block => match parse_block(item, source) {
+            Ok(b) => Some(Ok(Statement::Block(b))),
+            Err(e) => Some(Err(e)),
+        },
+        #[cfg(feature = "inheritance")]
+        Rule::parent_block => Some(Ok(Statement::Parent(Parent))),
+        #[cfg(not(feature = "inheritance"))]
+        Rule::extends | Rule::block | Rule::parent_block => Some(Err(
+            ParseError::disabled_feature(&item, source, "inheritance"),
+        )),
+        Rule::comment | Rule::EOI => None,
         _ => unreachable!("Unexpected rule {:#?}", item.as_rule()),
     }
 }
 
 #[cfg(feature = "conditional")]
-fn parse_conditional(conditional: Pair<Rule>) -> Result<Statement, ParseError> {
+fn parse_conditional(conditional: Pair<Rule>, source: &Source) -> Result<Statement, ParseError> {
     assert_eq!(conditional.as_rule(), Rule::conditional);
     let mut conditional = conditional.into_inner();
 
-    let condition = conditional.next().unwrap();
-    let condition = parse_condition(condition);
-    let then_case = conditional
-        .next()
-        .unwrap()
-        .into_inner()
-        .filter_map(parse_template_content)
-        .collect::<Result<Vec<_>, _>>()?;
-    let else_case = conditional.next().map(|else_case| {
-        else_case
-            .into_inner()
-            .filter_map(parse_template_content)
-            .collect::<Result<Vec<_>, _>>()
-    });
-
-    let else_case = if let Some(e) = else_case {
-        Some(e?)
-    } else {
-        None
-    };
+    let condition = tag_inner(conditional.next().unwrap(), Rule::if_tag).next().unwrap();
+    let span = Span::new(substr(&condition, source));
+    let condition = parse_condition(condition, source);
+    let mut errors = Errors::default();
+    let then_case = errors
+        .take(parse_statements(conditional.next().unwrap(), source))
+        .unwrap_or_default();
+
+    let mut else_if_cases = Vec::new();
+    let mut else_case = None;
+    while let Some(item) = conditional.next() {
+        match item.as_rule() {
+            Rule::elseif_tag => {
+                let condition = parse_condition(item.into_inner().next().unwrap(), source);
+                let case = errors
+                    .take(parse_statements(conditional.next().unwrap(), source))
+                    .unwrap_or_default();
+                else_if_cases.push((condition, case));
+            }
+            Rule::else_tag => {
+                else_case = Some(
+                    errors
+                        .take(parse_statements(conditional.next().unwrap(), source))
+                        .unwrap_or_default(),
+                );
+            }
+            Rule::endif_tag => {}
+            _ => unreachable!("Unexpected rule {:#?}", item.as_rule()),
+        }
+    }
 
-    Ok(Statement::Condition(Conditional {
+    errors.finish(Statement::Condition(Box::new(Conditional {
         condition,
         then_case,
+        else_if_cases,
         else_case,
-    }))
+        span,
+    })))
 }
 
 #[cfg(feature = "condition")]
-fn parse_condition(condition: Pair<Rule>) -> Condition {
+fn parse_condition(condition: Pair<Rule>, source: &Source) -> Condition {
     assert_eq!(condition.as_rule(), Rule::condition);
     let mut inner = condition.into_inner();
 
@@ -120,9 +370,9 @@ fn parse_condition(condition: Pair<Rule>) -> Condition {
     // At some point no more operators will be found and the function returns
     while let Some(c) = inner.next() {
         let c = match c.as_rule() {
-            Rule::condition => parse_condition(c),
-            Rule::compare_condition => Condition::Compare(parse_compare_condition(c)),
-            Rule::calculated_value => Condition::CalculatedValue(parse_calculated_value(c)),
+            Rule::condition => parse_condition(c, source),
+            Rule::compare_condition => Condition::Compare(parse_compare_condition(c, source)),
+            Rule::calculated_value => Condition::CalculatedValue(parse_calculated_value(c, source)),
             _ => unreachable!(),
         };
 
@@ -163,19 +413,20 @@ fn parse_condition(condition: Pair<Rule>) -> Condition {
     unreachable!()
 }
 
-fn parse_calculated(calculated: Pair<Rule>) -> Statement {
+fn parse_calculated(calculated: Pair<Rule>, source: &Source) -> Statement {
     assert_eq!(calculated.as_rule(), Rule::calculated);
+    let tag = Span::new(substr(&calculated, source));
     let inner = calculated.into_inner().next().unwrap();
-    Statement::Calculated(parse_calculated_value(inner))
+    Statement::Calculated(parse_calculated_value(inner, source).with_tag(tag))
 }
 
 #[cfg(feature = "condition")]
-fn parse_compare_condition(compare_condition: Pair<Rule>) -> CompareCondition {
+fn parse_compare_condition(compare_condition: Pair<Rule>, source: &Source) -> CompareCondition {
     assert_eq!(compare_condition.as_rule(), Rule::compare_condition);
     let mut inner = compare_condition.into_inner();
-    let calc_val_l = parse_calculated_value(inner.next().unwrap());
+    let calc_val_l = parse_calculated_value(inner.next().unwrap(), source);
     let operator = parse_compare_operator(inner.next().unwrap());
-    let calc_val_r = parse_calculated_value(inner.next().unwrap());
+    let calc_val_r = parse_calculated_value(inner.next().unwrap(), source);
     CompareCondition {
         left: calc_val_l,
         operator,
@@ -198,88 +449,467 @@ fn parse_compare_operator(compare_operator: Pair<Rule>) -> CompareOperator {
     }
 }
 
-fn parse_calculated_value(calculated_value: Pair<Rule>) -> CalculatedValue {
+fn parse_calculated_value(calculated_value: Pair<Rule>, source: &Source) -> CalculatedValue {
     assert_eq!(calculated_value.as_rule(), Rule::calculated_value);
-    let mut inner = calculated_value.into_inner();
-    let value = parse_value(inner.next().unwrap());
-    let modifiers = inner.into_iter().map(parse_modifier).collect::<Vec<_>>();
-    CalculatedValue::new(value, modifiers)
+    parse_operation(calculated_value, source)
+}
+
+/// Parses `calculated_value`, `sum` and `product`. Operators of the same precedence are
+/// applied from left to right.
+fn parse_operation(operation: Pair<Rule>, source: &Source) -> CalculatedValue {
+    let start = operation.as_span().start();
+    let mut inner = operation.into_inner();
+    let mut value = parse_operand(inner.next().unwrap(), source);
+    while let Some(operator) = inner.next() {
+        let right = inner.next().unwrap();
+        let span = Span::new(source.substr(start..right.as_span().end()));
+        let operator_span = Span::new(substr(&operator, source));
+        let operator = match operator.as_rule() {
+            Rule::concat_operator => Operator::Concat,
+            Rule::add_operator => Operator::Add,
+            Rule::sub_operator => Operator::Sub,
+            Rule::mul_operator => Operator::Mul,
+            Rule::div_operator => Operator::Div,
+            Rule::rem_operator => Operator::Rem,
+            rule => unreachable!("unexpected operator {:?}", rule),
+        };
+        let right = parse_operand(right, source);
+        value = CalculatedValue::operation(value, operator, operator_span, right).with_span(span);
+    }
+    value
+}
+
+fn parse_operand(operand: Pair<Rule>, source: &Source) -> CalculatedValue {
+    match operand.as_rule() {
+        Rule::calculated_value | Rule::sum | Rule::product => parse_operation(operand, source),
+        Rule::unary => parse_unary(operand, source),
+        rule => unreachable!("unexpected operand {:?}", rule),
+    }
 }
 
-fn parse_modifier(item: Pair<Rule>) -> (*const str, Vec<StorageMethod>) {
+fn parse_unary(unary: Pair<Rule>, source: &Source) -> CalculatedValue {
+    assert_eq!(unary.as_rule(), Rule::unary);
+    let span = Span::new(substr(&unary, source));
+    let mut inner = unary.into_inner();
+    let first = inner.next().unwrap();
+    match first.as_rule() {
+        Rule::postfix => parse_postfix(first, source),
+        Rule::neg_operator => {
+            let operator = Span::new(substr(&first, source));
+            let value = parse_unary(inner.next().unwrap(), source);
+            CalculatedValue::negate(value, operator).with_span(span)
+        }
+        rule => unreachable!("unexpected unary {:?}", rule),
+    }
+}
+
+/// Parses a value or a parenthesised expression followed by modifiers.
+fn parse_postfix(postfix: Pair<Rule>, source: &Source) -> CalculatedValue {
+    assert_eq!(postfix.as_rule(), Rule::postfix);
+    let span = Span::new(substr(&postfix, source));
+    let mut inner = postfix.into_inner();
+    let first = inner.next().unwrap();
+    let modifiers = inner.map(|modifier| parse_modifier(modifier, source)).collect::<Vec<_>>();
+    match first.as_rule() {
+        Rule::value => CalculatedValue::new(parse_value(first, source), modifiers),
+        _ => parse_calculated_value(first, source).with_modifiers(modifiers),
+    }
+    .with_span(span)
+}
+
+fn parse_modifier(item: Pair<Rule>, source: &Source) -> (Substr, Vec<StorageMethod>) {
     assert_eq!(item.as_rule(), Rule::modifier);
     let mut items = item.into_inner();
-    let name = items.next().unwrap().as_str();
-    (name, items.map(parse_argument).collect())
+    let name = substr(&items.next().unwrap(), source);
+    (name, items.map(|argument| parse_argument(argument, source)).collect())
 }
 
-fn parse_argument(argument: Pair<Rule>) -> StorageMethod {
+fn parse_argument(argument: Pair<Rule>, source: &Source) -> StorageMethod {
     assert_eq!(argument.as_rule(), Rule::argument);
     let value = argument.into_inner().next().unwrap();
-    parse_value(value)
+    parse_value(value, source)
 }
 
-fn parse_value(value: Pair<Rule>) -> StorageMethod {
+fn parse_value(value: Pair<Rule>, source: &Source) -> StorageMethod {
     assert_eq!(value.as_rule(), Rule::value);
     let value = value.into_inner().next().unwrap();
     match value.as_rule() {
-        Rule::identifier => StorageMethod::Variable(value.as_str()),
-        Rule::number => StorageMethod::Const(Value::Number(value.as_str().parse().unwrap())),
-        Rule::string => StorageMethod::Const(Value::String(
-            value.into_inner().next().unwrap().as_str().replace("\\\"", "\""),
-        )),
+        Rule::variable => parse_variable(value, source),
+        _ => StorageMethod::Const(parse_literal(value)),
+    }
+}
+
+fn parse_variable(variable: Pair<Rule>, source: &Source) -> StorageMethod {
+    assert_eq!(variable.as_rule(), Rule::variable);
+    let path = substr(&variable, source);
+    let start = variable.as_span().start();
+    let mut inner = variable.into_inner();
+    let root = substr(&inner.next().unwrap(), source);
+
+    let segments = inner
+        .map(|segment| {
+            let end = segment.as_span().end() - start;
+            let rule = segment.as_rule();
+            let inner = segment.into_inner().next().unwrap();
+            let segment = match rule {
+                Rule::key_segment => PathSegment::Key(substr(&inner, source)),
+                Rule::index_segment => {
+                    PathSegment::Index(inner.as_str().parse().expect("checked by check_indices"))
+                }
+                _ => unreachable!("Unexpected path segment {:#?}", rule),
+            };
+            (end, segment)
+        })
+        .collect::<Vec<_>>();
+
+    if segments.is_empty() {
+        StorageMethod::Variable(root)
+    } else {
+        StorageMethod::Path(VariablePath::new(path, root, segments))
+    }
+}
+
+fn parse_literal(literal: Pair<Rule>) -> Value {
+    match literal.as_rule() {
+        Rule::number => match literal.as_str().parse() {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::Number(literal.as_str().parse().unwrap()),
+        },
+        Rule::string => Value::String(
+            literal
+                .into_inner()
+                .next()
+                .unwrap()
+                .as_str()
+                .replace("\\\"", "\""),
+        ),
         Rule::boolean => {
-            let value = match value.as_str() {
+            let value = match literal.as_str() {
                 "true" => true,
                 "false" => false,
                 _ => unreachable!("boolean must be true or false"),
             };
-            StorageMethod::Const(Value::Bool(value))
+            Value::Bool(value)
         }
-        _ => unreachable!("Unexpected value {:#?}", value),
+        Rule::array => Value::Array(literal.into_inner().map(parse_literal).collect()),
+        _ => unreachable!("Unexpected literal {:#?}", literal),
     }
 }
 
 #[cfg(feature = "assign")]
-fn parse_assign(assign: Pair<Rule>) -> Assign {
+fn parse_assign(assign: Pair<Rule>, source: &Source) -> Assign {
     assert_eq!(assign.as_rule(), Rule::assign);
+    let span = Span::new(substr(&assign, source));
     let mut inner = assign.into_inner();
     let ident = inner.next().unwrap();
     assert_eq!(ident.as_rule(), Rule::identifier);
-    let ident = ident.as_str();
-    let calc_val = parse_calculated_value(inner.next().unwrap());
-    Assign::new(ident, calc_val)
+    let ident = substr(&ident, source);
+    let calc_val = parse_calculated_value(inner.next().unwrap(), source);
+    Assign::new(ident, calc_val).with_span(span)
 }
 
 #[cfg(feature = "loop")]
-fn parse_loop(l: Pair<Rule>) -> Result<Loop, ParseError> {
+fn parse_loop(l: Pair<Rule>, source: &Source) -> Result<Loop, ParseError> {
     assert_eq!(l.as_rule(), Rule::while_loop);
     let mut inner = l.into_inner();
-    let condition = parse_condition(inner.next().unwrap());
-    let template = inner
-        .next()
-        .unwrap()
-        .into_inner()
-        .filter_map(parse_template_content)
-        .collect::<Result<Vec<_>, _>>()?;
-    Ok(Loop::new(condition, template))
+    let condition = tag_inner(inner.next().unwrap(), Rule::while_tag).next().unwrap();
+    let span = Span::new(substr(&condition, source));
+    let condition = parse_condition(condition, source);
+    let template = parse_statements(inner.next().unwrap(), source)?;
+    Ok(Loop::new(condition, template).with_span(span))
 }
 
-#[derive(Debug)]
-pub enum ParseError {
-    Pos((usize, usize)),
-    Span((usize, usize), (usize, usize)),
-    DisabledFeature(UnsupportedFeature),
+#[cfg(feature = "foreach")]
+fn parse_foreach(f: Pair<Rule>, source: &Source) -> Result<Foreach, ParseError> {
+    assert_eq!(f.as_rule(), Rule::foreach_loop);
+    let mut inner = f.into_inner();
+    let mut tag = tag_inner(inner.next().unwrap(), Rule::foreach_tag);
+    let ident = tag.next().unwrap();
+    assert_eq!(ident.as_rule(), Rule::identifier);
+    let ident = substr(&ident, source);
+    let collection = parse_calculated_value(tag.next().unwrap(), source);
+    let mut errors = Errors::default();
+    let template = errors
+        .take(parse_statements(inner.next().unwrap(), source))
+        .unwrap_or_default();
+    let else_case = match inner.next().unwrap().as_rule() {
+        Rule::else_tag => Some(
+            errors
+                .take(parse_statements(inner.next().unwrap(), source))
+                .unwrap_or_default(),
+        ),
+        _ => None,
+    };
+    errors.finish(Foreach::new(ident, collection, template, else_case))
 }
 
-#[derive(Debug)]
-pub enum UnsupportedFeature {
-    #[cfg(not(feature = "assign"))]
-    Assign,
-    #[cfg(not(feature = "conditional"))]
-    Conditional,
-    #[cfg(not(feature = "loop"))]
-    Loop,
+#[cfg(feature = "include")]
+fn parse_include(include: Pair<Rule>, source: &Source) -> Include {
+    assert_eq!(include.as_rule(), Rule::include);
+    let span = Span::new(substr(&include, source));
+    let mut inner = include.into_inner();
+    let template = parse_value(inner.next().unwrap(), source);
+    let assigns = inner
+        .map(|assign| {
+            assert_eq!(assign.as_rule(), Rule::include_assign);
+            let span = Span::new(substr(&assign, source));
+            let mut inner = assign.into_inner();
+            let ident = substr(&inner.next().unwrap(), source);
+            Assign::new(ident, parse_calculated_value(inner.next().unwrap(), source))
+                .with_span(span)
+        })
+        .collect();
+    Include::new(template, assigns).with_span(span)
+}
+
+#[cfg(feature = "inheritance")]
+fn parse_extends(extends: Pair<Rule>, source: &Source) -> Extends {
+    assert_eq!(extends.as_rule(), Rule::extends);
+    let span = Span::new(substr(&extends, source));
+    let template = parse_value(extends.into_inner().next().unwrap(), source);
+    Extends::new(template).with_span(span)
+}
+
+#[cfg(feature = "inheritance")]
+fn parse_block(block: Pair<Rule>, source: &Source) -> Result<Block, ParseError> {
+    assert_eq!(block.as_rule(), Rule::block);
+    let mut inner = block.into_inner();
+    let name = tag_inner(inner.next().unwrap(), Rule::block_tag).next().unwrap();
+    assert_eq!(name.as_rule(), Rule::identifier);
+    let name = substr(&name, source);
+    let template = parse_statements(inner.next().unwrap(), source)?;
+    Ok(Block::new(name, template))
+}
+
+/// Collects the errors of several parts of a template, so one parse reports as many errors as
+/// possible.
+#[derive(Default)]
+struct Errors(Vec<Diagnostic>);
+
+impl Errors {
+    fn take<T>(&mut self, result: Result<T, ParseError>) -> Option<T> {
+        result.map_err(|e| self.0.extend(e.diagnostics)).ok()
+    }
+
+    fn finish<T>(self, value: T) -> Result<T, ParseError> {
+        if self.0.is_empty() {
+            Ok(value)
+        } else {
+            Err(ParseError {
+                template: None,
+                diagnostics: self.0,
+            })
+        }
+    }
+}
+
+/// A template could not be parsed.
+///
+/// Parsing stops at the first syntax error, which is then the only diagnostic. Errors found in a
+/// syntactically valid template, like statements of disabled features or indices too large for
+/// `usize`, are all reported. The `Display` implementation prints each error with the offending
+/// line and a caret below it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    /// Name of the template. It is set by [`crate::MiniTemplate::add_template`].
+    pub template: Option<String>,
+    /// The errors in order of their occurrence. Never empty and contains a single syntax error at
+    /// most.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A single error inside a template.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    /// Human readable description like ``expected value, found `}` ``
+    pub message: String,
+    /// Names of the tokens that would have been valid at the position of the error
+    pub expected: Vec<String>,
+    pub position: Position,
+}
+
+impl ParseError {
+    fn new(diagnostic: Diagnostic) -> Self {
+        Self {
+            template: None,
+            diagnostics: vec![diagnostic],
+        }
+    }
+
+    fn from_pest(error: pest::error::Error<Rule>, source: &Source) -> Self {
+        let (start, end) = match error.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let (start, end) = (source.offset(start), source.offset(end));
+        let mut expected = Vec::new();
+        if let ErrorVariant::ParsingError { positives, .. } = &error.variant {
+            for name in positives.iter().map(|rule| rule_name(rule, &source.options.delimiters)) {
+                if !expected.contains(&name) {
+                    expected.push(name);
+                }
+            }
+        }
+        let text = &source.text;
+        let rest = &text[start..];
+        let word = rest
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .map_or(rest, |end| &rest[..end]);
+        let delimiters = &source.options.delimiters;
+        let delimiter = [delimiters.open(), delimiters.close()]
+            .into_iter()
+            .find(|d| rest.starts_with(d));
+        let found = match rest.chars().next() {
+            None => String::from("end of template"),
+            Some('\n') => String::from("line break"),
+            Some(_) if !word.is_empty() => format!("`{}`", word),
+            Some(c) => format!("`{}`", delimiter.map_or_else(|| c.to_string(), str::to_owned)),
+        };
+        let message = misplaced_tag(&text[..start], word, delimiters).unwrap_or_else(|| {
+            match expected.split_last() {
+                None => format!("unexpected {}", found),
+                Some((last, [])) => format!("expected {}, found {}", last, found),
+                Some((last, others)) => {
+                    format!("expected {} or {}, found {}", others.join(", "), last, found)
+                }
+            }
+        });
+        Self::new(Diagnostic {
+            message,
+            expected,
+            position: Position::new(text, start, &text[start..end]),
+        })
+    }
+
+    // Only used if a feature is disabled
+    #[allow(dead_code)]
+    fn disabled_feature(item: &Pair<Rule>, source: &Source, feature: &str) -> Self {
+        Self::at(item, source, format!("the {} feature is disabled", feature))
+    }
+
+    /// Creates an error pointing at `item`.
+    fn at(item: &Pair<Rule>, source: &Source, message: String) -> Self {
+        let span = item.as_span();
+        let start = source.offset(span.start());
+        Self::new(Diagnostic {
+            message,
+            expected: Vec::new(),
+            position: Position::new(&source.text, start, &source.substr(span.start()..span.end())),
+        })
+    }
+
+    pub(crate) fn with_template(mut self, template: String) -> Self {
+        self.template = Some(template);
+        self
+    }
+}
+
+/// Describes branch and end tags like `{elseif}` after `{else}` that are not allowed where
+/// they are. `before` is the template up to `word`.
+fn misplaced_tag(before: &str, word: &str, delimiters: &Delimiters) -> Option<String> {
+    let before = before.trim_end();
+    if !before.strip_suffix('-').unwrap_or(before).ends_with(delimiters.open()) {
+        return None;
+    }
+    let tag = |word: &str| format!("`{}{}{}`", delimiters.open(), word, delimiters.close());
+    let message = match word {
+        "elseif" | "elif" => format!(
+            "{} is only allowed inside of {} before {}",
+            tag(word),
+            tag("if"),
+            tag("else")
+        ),
+        "else" => format!(
+            "{} is only allowed once inside of {} or {}",
+            tag(word),
+            tag("if"),
+            tag("foreach")
+        ),
+        "endif" | "endwhile" | "endforeach" | "endblock" => {
+            format!("{} has no matching {}", tag(word), tag(&word[3..]))
+        }
+        _ => return None,
+    };
+    Some(message)
+}
+
+/// Describes a rule in error messages.
+fn rule_name(rule: &Rule, delimiters: &Delimiters) -> String {
+    let name = match rule {
+        Rule::EOI => "end of template",
+        Rule::calculated_value
+        | Rule::sum
+        | Rule::product
+        | Rule::unary
+        | Rule::postfix
+        | Rule::value
+        | Rule::variable => "value",
+        Rule::literal => "literal",
+        Rule::argument => "modifier argument",
+        Rule::compare_operator
+        | Rule::eq_operator
+        | Rule::ne_operator
+        | Rule::lt_operator
+        | Rule::le_operator
+        | Rule::gt_operator
+        | Rule::ge_operator => "comparison operator",
+        Rule::concat_operator
+        | Rule::add_operator
+        | Rule::sub_operator
+        | Rule::mul_operator
+        | Rule::div_operator
+        | Rule::rem_operator => "operator",
+        Rule::neg_operator => "`-`",
+        Rule::and_operator => "`&&`",
+        Rule::or_operator => "`||`",
+        Rule::key_segment => "`.`",
+        Rule::comment_end => return format!("`*{}`", delimiters.close()),
+        Rule::index_segment => "`[`",
+        Rule::template_content | Rule::text | Rule::literal_content => "text",
+        Rule::endliteral_tag => {
+            return format!("`{}/literal{}`", delimiters.open(), delimiters.close())
+        }
+        Rule::calculated
+        | Rule::assign
+        | Rule::include
+        | Rule::include_assign
+        | Rule::extends
+        | Rule::literal_block
+        | Rule::block
+        | Rule::parent_block
+        | Rule::conditional
+        | Rule::while_loop
+        | Rule::foreach_loop => "statement",
+        rule => return format!("{:?}", rule).replace('_', " "),
+    };
+    name.to_owned()
+}
+
+impl std::error::Error for ParseError {}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let position = &diagnostic.position;
+            match &self.template {
+                Some(template) => writeln!(
+                    f,
+                    "{} (template {}, line {}, column {})",
+                    diagnostic.message, template, position.line, position.column
+                )?,
+                None => writeln!(
+                    f,
+                    "{} (line {}, column {})",
+                    diagnostic.message, position.line, position.column
+                )?,
+            }
+            position.write_snippet(f)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -288,6 +918,10 @@ mod tests {
 
     use super::*;
 
+    fn source(text: &str) -> Source {
+        Source::new(Arc::from(text), ParseOptions::default())
+    }
+
     #[test]
     fn parse_template_item_literal() {
         let template = String::from("test literal");
@@ -296,21 +930,22 @@ mod tests {
         let item = item.unwrap().next();
         assert!(item.is_some());
         let item = item.unwrap();
-        let statement = parse_template_content(item).unwrap().unwrap();
-        assert_eq!(statement, Statement::Literal("test literal"))
+        let statement = parse_template_content(item, &source(&template)).unwrap().unwrap();
+        assert_eq!(statement, Statement::Literal("test literal".into()))
     }
 
     #[test]
     fn parse_template_single_literal() {
         let template = String::from("test literal");
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok());
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
-                tpl: vec![Statement::Literal("test literal")],
-                tpl_str: String::from("test literal")
+                name: None,
+                tpl: vec![Statement::Literal("test literal".into())],
+                tpl_str: "test literal".into()
             }
         );
     }
@@ -323,11 +958,11 @@ mod tests {
         let item = item.unwrap().next();
         assert!(item.is_some());
         let item = item.unwrap();
-        let statement = parse_calculated(item);
+        let statement = parse_calculated(item, &source(&template));
         assert_eq!(
             statement,
             Statement::Calculated(CalculatedValue::new(
-                StorageMethod::Variable("var"),
+                StorageMethod::Variable("var".into()),
                 Vec::new()
             ))
         )
@@ -336,17 +971,18 @@ mod tests {
     #[test]
     fn parse_template_single_computed() {
         let template = String::from("{var}");
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok());
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
+                name: None,
                 tpl: vec![Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
+                    StorageMethod::Variable("var".into()),
                     vec![],
                 ))],
-                tpl_str: String::from("{var}")
+                tpl_str: "{var}".into()
             }
         );
     }
@@ -354,16 +990,17 @@ mod tests {
     #[test]
     fn parse_template_single_computed_modifier() {
         let template = String::from("{var|modifier}");
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok());
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
-                tpl_str: String::from("{var|modifier}"),
+                name: None,
+                tpl_str: "{var|modifier}".into(),
                 tpl: vec![Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
-                    vec![("modifier", vec![])]
+                    StorageMethod::Variable("var".into()),
+                    vec![("modifier".into(), vec![])]
                 ))]
             }
         )
@@ -372,16 +1009,17 @@ mod tests {
     #[test]
     fn parse_template_single_computed_multiple_modifier() {
         let template = String::from("{var|modifier1|modifier2}");
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok());
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
-                tpl_str: String::from("{var|modifier1|modifier2}"),
+                name: None,
+                tpl_str: "{var|modifier1|modifier2}".into(),
                 tpl: vec![Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
-                    vec![("modifier1", vec![]), ("modifier2", vec![])]
+                    StorageMethod::Variable("var".into()),
+                    vec![("modifier1".into(), vec![]), ("modifier2".into(), vec![])]
                 ))]
             }
         )
@@ -390,16 +1028,17 @@ mod tests {
     #[test]
     fn parse_template_single_computed_modifier_var_param() {
         let template = String::from("{var|modifier:var2}");
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok());
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
-                tpl_str: String::from("{var|modifier:var2}"),
+                name: None,
+                tpl_str: "{var|modifier:var2}".into(),
                 tpl: vec![Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
-                    vec![("modifier", vec![StorageMethod::Variable("var2")])]
+                    StorageMethod::Variable("var".into()),
+                    vec![("modifier".into(), vec![StorageMethod::Variable("var2".into())])]
                 ))]
             }
         )
@@ -408,17 +1047,18 @@ mod tests {
     #[test]
     fn parse_template_single_computed_modifier_number_param() {
         let template = String::from(r#"{var|modifier:-32.09}"#);
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok());
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
-                tpl_str: String::from(r#"{var|modifier:-32.09}"#),
+                name: None,
+                tpl_str: r#"{var|modifier:-32.09}"#.into(),
                 tpl: vec![Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
+                    StorageMethod::Variable("var".into()),
                     vec![(
-                        "modifier",
+                        "modifier".into(),
                         vec![StorageMethod::Const(Value::Number(-32.09))]
                     )]
                 ))]
@@ -429,17 +1069,18 @@ mod tests {
     #[test]
     fn parse_template_single_computed_literal_before_modifier() {
         let template = String::from(r#"{10|modifier:-32.09}"#);
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok());
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
-                tpl_str: String::from(r#"{10|modifier:-32.09}"#),
+                name: None,
+                tpl_str: r#"{10|modifier:-32.09}"#.into(),
                 tpl: vec![Statement::Calculated(CalculatedValue::new(
                     StorageMethod::Const(Value::Number(10.0)),
                     vec![(
-                        "modifier",
+                        "modifier".into(),
                         vec![StorageMethod::Const(Value::Number(-32.09))]
                     )]
                 ))]
@@ -450,21 +1091,22 @@ mod tests {
     #[test]
     fn parse_template_single_computed_modifier_multiple_args() {
         let template = String::from(r#"{var|modifier:-32.09:"argument":var2:true}"#);
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok(), "{:#?}", template);
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
-                tpl_str: String::from(r#"{var|modifier:-32.09:"argument":var2:true}"#),
+                name: None,
+                tpl_str: r#"{var|modifier:-32.09:"argument":var2:true}"#.into(),
                 tpl: vec![Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
+                    StorageMethod::Variable("var".into()),
                     vec![(
-                        "modifier",
+                        "modifier".into(),
                         vec![
                             StorageMethod::Const(Value::Number(-32.09)),
                             StorageMethod::Const(Value::String(String::from("argument"))),
-                            StorageMethod::Variable("var2"),
+                            StorageMethod::Variable("var2".into()),
                             StorageMethod::Const(Value::Bool(true))
                         ]
                     )]
@@ -473,26 +1115,76 @@ mod tests {
         )
     }
 
+    #[test]
+    fn parse_template_single_computed_array_literal() {
+        let template = String::from(r#"{var|modifier:[1, "two", [true]]}"#);
+        let template = parse(template, ParseOptions::default());
+        assert!(template.is_ok(), "{:#?}", template);
+        let template = template.unwrap();
+        assert_eq!(
+            template,
+            Template {
+                name: None,
+                tpl_str: r#"{var|modifier:[1, "two", [true]]}"#.into(),
+                tpl: vec![Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Variable("var".into()),
+                    vec![(
+                        "modifier".into(),
+                        vec![StorageMethod::Const(Value::Array(vec![
+                            Value::Number(1.),
+                            Value::String(String::from("two")),
+                            Value::Array(vec![Value::Bool(true)])
+                        ]))]
+                    )]
+                ))]
+            }
+        )
+    }
+
+    #[test]
+    fn parse_template_single_computed_path() {
+        let template = String::from("{items[0].name}");
+        let template = parse(template, ParseOptions::default());
+        assert!(template.is_ok(), "{:#?}", template);
+        let template = template.unwrap();
+        assert_eq!(
+            template,
+            Template {
+                name: None,
+                tpl_str: "{items[0].name}".into(),
+                tpl: vec![Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Path(VariablePath::new(
+                        "items[0].name".into(),
+                        "items".into(),
+                        vec![(8, PathSegment::Index(0)), (13, PathSegment::Key("name".into()))]
+                    )),
+                    vec![]
+                ))]
+            }
+        )
+    }
+
     #[test]
     fn parse_template_multi_line() {
         let template = String::from("{var|modifier}\n{10|modifier:-32.09}");
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok());
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
-                tpl_str: String::from("{var|modifier}\n{10|modifier:-32.09}"),
+                name: None,
+                tpl_str: "{var|modifier}\n{10|modifier:-32.09}".into(),
                 tpl: vec![
                     Statement::Calculated(CalculatedValue::new(
-                        StorageMethod::Variable("var"),
-                        vec![("modifier", vec![])]
+                        StorageMethod::Variable("var".into()),
+                        vec![("modifier".into(), vec![])]
                     )),
-                    Statement::Literal("\n"),
+                    Statement::Literal("\n".into()),
                     Statement::Calculated(CalculatedValue::new(
                         StorageMethod::Const(Value::Number(10.0)),
                         vec![(
-                            "modifier",
+                            "modifier".into(),
                             vec![StorageMethod::Const(Value::Number(-32.09))]
                         )]
                     ))
@@ -505,19 +1197,20 @@ mod tests {
     #[test]
     fn parse_template_assign() {
         let template = String::from("{var = 10|modifier:-32.09}");
-        let template = parse(template);
+        let template = parse(template, ParseOptions::default());
         assert!(template.is_ok(), "{template:#?}");
         let template = template.unwrap();
         assert_eq!(
             template,
             Template {
-                tpl_str: String::from("{var = 10|modifier:-32.09}"),
+                name: None,
+                tpl_str: "{var = 10|modifier:-32.09}".into(),
                 tpl: vec![Statement::Assign(Assign::new(
-                    "var",
+                    "var".into(),
                     CalculatedValue::new(
                         StorageMethod::Const(Value::Number(10.0)),
                         vec![(
-                            "modifier",
+                            "modifier".into(),
                             vec![StorageMethod::Const(Value::Number(-32.09))]
                         )]
                     )
@@ -526,6 +1219,261 @@ mod tests {
         )
     }
 
+    #[test]
+    fn parse_template_operators() {
+        let template = parse(String::from("{ -a + b * (c ~ d)|upper}"), ParseOptions::default());
+        let var = |name: &str| CalculatedValue::new(StorageMethod::Variable(name.into()), vec![]);
+        let concat =
+            CalculatedValue::operation(var("c"), Operator::Concat, Span::default(), var("d"));
+        let product = CalculatedValue::operation(
+            var("b"),
+            Operator::Mul,
+            Span::default(),
+            concat.with_modifiers(vec![("upper".into(), vec![])]),
+        );
+        assert_eq!(
+            template.unwrap().tpl,
+            vec![Statement::Calculated(CalculatedValue::operation(
+                CalculatedValue::negate(var("a"), Span::default()),
+                Operator::Add,
+                Span::default(),
+                product,
+            ))]
+        );
+    }
+
+    #[test]
+    fn operators_are_left_associative() {
+        let template = parse(String::from("{a - b - 1}"), ParseOptions::default());
+        let var = |name: &str| CalculatedValue::new(StorageMethod::Variable(name.into()), vec![]);
+        let left = CalculatedValue::operation(var("a"), Operator::Sub, Span::default(), var("b"));
+        let one = CalculatedValue::new(StorageMethod::Const(Value::Integer(1)), vec![]);
+        assert_eq!(
+            template.unwrap().tpl,
+            vec![Statement::Calculated(CalculatedValue::operation(
+                left,
+                Operator::Sub,
+                Span::default(),
+                one,
+            ))]
+        );
+    }
+
+    #[test]
+    fn negative_literal_and_trim_marker() {
+        let template = parse(String::from("{-1} {a -}"), ParseOptions::default()).unwrap();
+        assert_eq!(
+            template.tpl,
+            vec![
+                Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Const(Value::Integer(-1)),
+                    vec![]
+                )),
+                Statement::Literal(" ".into()),
+                Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Variable("a".into()),
+                    vec![]
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn ambiguous_trim_marker() {
+        for template in ["{-a}", "x {-(a + 1)-}"] {
+            let error = parse(String::from(template), ParseOptions::default()).unwrap_err();
+            assert_eq!(
+                error.diagnostics[0].message,
+                "`{-` is ambiguous, write `{- value}` to trim whitespace or `{(-value)}` to negate"
+            );
+        }
+        let options = ParseOptions {
+            delimiters: Delimiters::new("<%", "%>"),
+            ..ParseOptions::default()
+        };
+        let error = parse(String::from("<%-a%>"), options).unwrap_err();
+        assert_eq!(error.diagnostics[0].position.column, 1);
+        assert_eq!(
+            error.diagnostics[0].message,
+            "`<%-` is ambiguous, write `<%- value%>` to trim whitespace or `<%(-value)%>` to negate"
+        );
+        assert!(parse(String::from("{- a}{-true|default:1}"), ParseOptions::default()).is_err());
+        assert!(parse(String::from("{- a}{-1}{-\"a\"}"), ParseOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn integer_literals() {
+        let number = |input| {
+            parse_literal(TemplateParser::parse(Rule::number, input).unwrap().next().unwrap())
+        };
+        assert!(matches!(number("18446744073709551617"), Value::Integer(18446744073709551617)));
+        assert!(matches!(number("-3"), Value::Integer(-3)));
+        assert!(matches!(number("3.0"), Value::Number(n) if n == 3.));
+    }
+
+    #[test]
+    fn comments_are_stripped() {
+        let template = parse(
+            String::from("a{* {var} {if b}\n*}c{*{*}d"),
+            ParseOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            template.tpl,
+            vec![
+                Statement::Literal("a".into()),
+                Statement::Literal("c".into()),
+                Statement::Literal("d".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn literal_block() {
+        let template = parse(
+            String::from("{literal}\nf({a: 1}) {* x *} {/literal}{-literal}{if}{/literal}"),
+            ParseOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            template.tpl,
+            vec![
+                Statement::Literal("\nf({a: 1}) {* x *} ".into()),
+                Statement::Literal("{if}".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_literal_block() {
+        let error = parse(String::from("{literal}{a}"), ParseOptions::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected `{/literal}`, found end of template (line 1, column 13)\n\
+            1 | {literal}{a}\n  |             ^"
+        );
+    }
+
+    #[test]
+    fn unclosed_comment() {
+        let error = parse(String::from("a{* note"), ParseOptions::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected `*}`, found end of template (line 1, column 9)\n1 | a{* note\n  |         ^"
+        );
+    }
+
+    #[test]
+    fn syntax_error() {
+        let error = parse(String::from("Hello\n{name|}"), ParseOptions::default()).unwrap_err();
+        assert_eq!(
+            error,
+            ParseError {
+                template: None,
+                diagnostics: vec![Diagnostic {
+                    message: String::from("expected identifier, found `}`"),
+                    expected: vec![String::from("identifier")],
+                    position: Position {
+                        line: 2,
+                        column: 7,
+                        length: 1,
+                        source_line: String::from("{name|}")
+                    }
+                }]
+            }
+        );
+        assert_eq!(
+            error.with_template(String::from("tpl")).to_string(),
+            "expected identifier, found `}` (template tpl, line 2, column 7)\n\
+            2 | {name|}\n  |       ^"
+        );
+    }
+
+    #[test]
+    fn oversized_index() {
+        let error = parse(
+            String::from("{items[1]}\n{items[99999999999999999999999]}"),
+            ParseOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "index 99999999999999999999999 is too large (line 2, column 8)\n\
+            2 | {items[99999999999999999999999]}\n  |        ^^^^^^^^^^^^^^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn syntax_error_found_word() {
+        let error = parse(
+            String::from("{if a}x{else}y{elseif b}z{endif}"),
+            ParseOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.diagnostics[0].message,
+            "`{elseif}` is only allowed inside of `{if}` before `{else}`"
+        );
+        assert_eq!(
+            error.diagnostics[0].expected,
+            vec![String::from("value"), String::from("identifier")]
+        );
+    }
+
+    #[test]
+    fn misplaced_tags() {
+        let message = |template: &str| {
+            let error = parse(String::from(template), ParseOptions::default()).unwrap_err();
+            error.diagnostics[0].message.clone()
+        };
+        assert_eq!(
+            message("{if a}x{else}y{else}z{endif}"),
+            "`{else}` is only allowed once inside of `{if}` or `{foreach}`"
+        );
+        assert_eq!(message("x{- endforeach}"), "`{endforeach}` has no matching `{foreach}`");
+        assert_eq!(message("{if a}x{endif}{endif}"), "`{endif}` has no matching `{if}`");
+        let options = ParseOptions {
+            delimiters: Delimiters::new("<%", "%>"),
+            ..ParseOptions::default()
+        };
+        let error = parse(String::from("<%if a%>x<%else%>y<%elif b%><%endif%>"), options);
+        assert_eq!(
+            error.unwrap_err().diagnostics[0].message,
+            "`<%elif%>` is only allowed inside of `<%if%>` before `<%else%>`"
+        );
+    }
+
+    #[test]
+    fn syntax_error_end_of_template() {
+        let error = parse(String::from("{name"), ParseOptions::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected modifier, operator, `.` or `[`, found end of template (line 1, column 6)\n\
+            1 | {name\n  |      ^"
+        );
+    }
+
+    #[cfg(not(feature = "conditional"))]
+    #[test]
+    fn reports_all_disabled_features() {
+        let error = parse(
+            String::from("{if a}x{endif}\n{if b}y{endif}"),
+            ParseOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error
+                .diagnostics
+                .iter()
+                .map(|d| (d.message.as_str(), d.position.line))
+                .collect::<Vec<_>>(),
+            vec![
+                ("the conditional feature is disabled", 1),
+                ("the conditional feature is disabled", 2)
+            ]
+        );
+    }
+
     #[cfg(feature = "conditional")]
     mod conditional {
         use super::*;
@@ -537,21 +1485,24 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let conditional_statement = parse_conditional(conditional).unwrap();
+            let conditional_statement =
+                parse_conditional(conditional, &source(template)).unwrap();
             if let Statement::Condition(conditional) = conditional_statement {
                 assert_eq!(
-                    conditional,
+                    *conditional,
                     Conditional {
                         condition: Condition::Compare(CompareCondition {
-                            left: CalculatedValue::new(StorageMethod::Variable("i"), vec![]),
+                            left: CalculatedValue::new(StorageMethod::Variable("i".into()), vec![]),
                             operator: CompareOperator::LT,
                             right: CalculatedValue::new(
                                 StorageMethod::Const(Value::Number(10.)),
                                 vec![]
                             )
                         }),
-                        then_case: vec![Statement::Literal("HI")],
-                        else_case: None
+                        then_case: vec![Statement::Literal("HI".into())],
+                        else_if_cases: vec![],
+                        else_case: None,
+                        span: Span::default()
                     }
                 )
             } else {
@@ -566,29 +1517,32 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let conditional_statement = parse_conditional(conditional).unwrap();
+            let conditional_statement =
+                parse_conditional(conditional, &source(template)).unwrap();
             if let Statement::Condition(conditional) = conditional_statement {
                 assert_eq!(
-                    conditional,
+                    *conditional,
                     Conditional {
                         condition: Condition::and(vec![
                             Condition::or(vec![
                                 Condition::CalculatedValue(CalculatedValue::new(
-                                    StorageMethod::Variable("var1"),
+                                    StorageMethod::Variable("var1".into()),
                                     vec![]
                                 )),
                                 Condition::CalculatedValue(CalculatedValue::new(
-                                    StorageMethod::Variable("var2"),
+                                    StorageMethod::Variable("var2".into()),
                                     vec![]
                                 ))
                             ]),
                             Condition::CalculatedValue(CalculatedValue::new(
-                                StorageMethod::Variable("var3"),
+                                StorageMethod::Variable("var3".into()),
                                 vec![]
                             ))
                         ]),
-                        then_case: vec![Statement::Literal("HI")],
-                        else_case: None
+                        then_case: vec![Statement::Literal("HI".into())],
+                        else_if_cases: vec![],
+                        else_case: None,
+                        span: Span::default()
                     }
                 )
             } else {
@@ -603,21 +1557,78 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let conditional_statement = parse_conditional(conditional).unwrap();
+            let conditional_statement =
+                parse_conditional(conditional, &source(template)).unwrap();
+            if let Statement::Condition(conditional) = conditional_statement {
+                assert_eq!(
+                    *conditional,
+                    Conditional {
+                        condition: Condition::Compare(CompareCondition {
+                            left: CalculatedValue::new(StorageMethod::Variable("i".into()), vec![]),
+                            operator: CompareOperator::LT,
+                            right: CalculatedValue::new(
+                                StorageMethod::Const(Value::Number(10.)),
+                                vec![]
+                            )
+                        }),
+                        then_case: vec![Statement::Literal("HI".into())],
+                        else_if_cases: vec![],
+                        else_case: Some(vec![Statement::Literal("TEST".into())]),
+                        span: Span::default()
+                    }
+                )
+            } else {
+                panic!("Unexpected statement")
+            }
+        }
+
+        #[test]
+        fn parse_else_if() {
+            let template = "{if i < 10}HI{elseif i < 20}HO{elif j}HU{else}TEST{endif}";
+            let conditional = TemplateParser::parse(Rule::conditional, template)
+                .unwrap()
+                .next()
+                .unwrap();
+            let conditional_statement =
+                parse_conditional(conditional, &source(template)).unwrap();
             if let Statement::Condition(conditional) = conditional_statement {
                 assert_eq!(
-                    conditional,
+                    *conditional,
                     Conditional {
                         condition: Condition::Compare(CompareCondition {
-                            left: CalculatedValue::new(StorageMethod::Variable("i"), vec![]),
+                            left: CalculatedValue::new(StorageMethod::Variable("i".into()), vec![]),
                             operator: CompareOperator::LT,
                             right: CalculatedValue::new(
                                 StorageMethod::Const(Value::Number(10.)),
                                 vec![]
                             )
                         }),
-                        then_case: vec![Statement::Literal("HI")],
-                        else_case: Some(vec![Statement::Literal("TEST")])
+                        then_case: vec![Statement::Literal("HI".into())],
+                        else_if_cases: vec![
+                            (
+                                Condition::Compare(CompareCondition {
+                                    left: CalculatedValue::new(
+                                        StorageMethod::Variable("i".into()),
+                                        vec![]
+                                    ),
+                                    operator: CompareOperator::LT,
+                                    right: CalculatedValue::new(
+                                        StorageMethod::Const(Value::Number(20.)),
+                                        vec![]
+                                    )
+                                }),
+                                vec![Statement::Literal("HO".into())]
+                            ),
+                            (
+                                Condition::CalculatedValue(CalculatedValue::new(
+                                    StorageMethod::Variable("j".into()),
+                                    vec![]
+                                )),
+                                vec![Statement::Literal("HU".into())]
+                            )
+                        ],
+                        else_case: Some(vec![Statement::Literal("TEST".into())]),
+                        span: Span::default()
                     }
                 )
             } else {
@@ -625,6 +1636,23 @@ mod tests {
             }
         }
 
+        #[test]
+        fn parse_else_if_error_position() {
+            let template = "{if a}x\n{elseif b}y\n{elif c ==}z{else}w{endif}";
+            let position = &super::super::parse(template.to_owned(), ParseOptions::default())
+                .unwrap_err()
+                .diagnostics[0]
+                .position;
+            assert_eq!((position.line, position.column), (3, 11));
+
+            let template = "{if a}x{elseif b}y{else}w{elseif c}z{endif}";
+            let position = &super::super::parse(template.to_owned(), ParseOptions::default())
+                .unwrap_err()
+                .diagnostics[0]
+                .position;
+            assert_eq!((position.line, position.column), (1, 27));
+        }
+
         #[test]
         fn parse_multiple() {
             let template = "{if i < 10}HI{else}{if n == \"TEST\"}HI2{else}TEST{endif}{endif}";
@@ -632,32 +1660,40 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let conditional_statement = parse_conditional(conditional).unwrap();
+            let conditional_statement =
+                parse_conditional(conditional, &source(template)).unwrap();
             if let Statement::Condition(conditional) = conditional_statement {
                 assert_eq!(
-                    conditional,
+                    *conditional,
                     Conditional {
                         condition: Condition::Compare(CompareCondition {
-                            left: CalculatedValue::new(StorageMethod::Variable("i"), vec![]),
+                            left: CalculatedValue::new(StorageMethod::Variable("i".into()), vec![]),
                             operator: CompareOperator::LT,
                             right: CalculatedValue::new(
                                 StorageMethod::Const(Value::Number(10.)),
                                 vec![]
                             )
                         }),
-                        then_case: vec![Statement::Literal("HI")],
-                        else_case: Some(vec![Statement::Condition(Conditional {
+                        then_case: vec![Statement::Literal("HI".into())],
+                        else_if_cases: vec![],
+                        else_case: Some(vec![Statement::Condition(Box::new(Conditional {
                             condition: Condition::Compare(CompareCondition {
-                                left: CalculatedValue::new(StorageMethod::Variable("n"), vec![]),
+                                left: CalculatedValue::new(
+                                    StorageMethod::Variable("n".into()),
+                                    vec![]
+                                ),
                                 operator: CompareOperator::EQ,
                                 right: CalculatedValue::new(
                                     StorageMethod::Const(Value::String("TEST".to_owned())),
                                     vec![]
                                 )
                             }),
-                            then_case: vec![Statement::Literal("HI2")],
-                            else_case: Some(vec![Statement::Literal("TEST")])
-                        })])
+                            then_case: vec![Statement::Literal("HI2".into())],
+                            else_if_cases: vec![],
+                            else_case: Some(vec![Statement::Literal("TEST".into())]),
+                            span: Span::default()
+                        }))]),
+                        span: Span::default()
                     }
                 )
             } else {
@@ -679,11 +1715,11 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let condition = super::parse_condition(condition);
+            let condition = super::parse_condition(condition, &source(template));
             assert_eq!(
                 condition,
                 Condition::CalculatedValue(CalculatedValue::new(
-                    StorageMethod::Variable("bar"),
+                    StorageMethod::Variable("bar".into()),
                     vec![]
                 )),
             );
@@ -696,11 +1732,11 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let condition = super::parse_condition(condition);
+            let condition = super::parse_condition(condition, &source(template));
             assert_eq!(
                 condition,
                 Condition::Compare(CompareCondition {
-                    left: CalculatedValue::new(StorageMethod::Variable("bar"), vec![]),
+                    left: CalculatedValue::new(StorageMethod::Variable("bar".into()), vec![]),
                     operator: CompareOperator::EQ,
                     right: CalculatedValue::new(StorageMethod::Const(Value::Number(10.)), vec![])
                 })
@@ -714,11 +1750,11 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let condition = super::parse_condition(condition);
+            let condition = super::parse_condition(condition, &source(template));
             assert_eq!(
                 condition,
                 Condition::Compare(CompareCondition {
-                    left: CalculatedValue::new(StorageMethod::Variable("bar"), vec![]),
+                    left: CalculatedValue::new(StorageMethod::Variable("bar".into()), vec![]),
                     operator: CompareOperator::EQ,
                     right: CalculatedValue::new(StorageMethod::Const(Value::Number(10.)), vec![])
                 })
@@ -732,21 +1768,21 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let condition = super::parse_condition(condition);
+            let condition = super::parse_condition(condition, &source(tpl));
             assert_eq!(
                 condition,
                 Condition::Or(OrCondition::new(vec![
                     Condition::CalculatedValue(CalculatedValue::new(
-                        StorageMethod::Variable("var1"),
+                        StorageMethod::Variable("var1".into()),
                         vec![]
                     )),
                     Condition::And(AndCondition::new(vec![
                         Condition::CalculatedValue(CalculatedValue::new(
-                            StorageMethod::Variable("var2"),
+                            StorageMethod::Variable("var2".into()),
                             vec![]
                         )),
                         Condition::CalculatedValue(CalculatedValue::new(
-                            StorageMethod::Variable("var3"),
+                            StorageMethod::Variable("var3".into()),
                             vec![]
                         ))
                     ]))
@@ -762,16 +1798,16 @@ mod tests {
                 .next()
                 .unwrap();
 
-            let condition = super::parse_condition(condition);
+            let condition = super::parse_condition(condition, &source(tpl));
             assert_eq!(
                 condition,
                 Condition::Or(OrCondition::new(vec![
                     Condition::CalculatedValue(CalculatedValue::new(
-                        StorageMethod::Variable("var1"),
+                        StorageMethod::Variable("var1".into()),
                         vec![]
                     )),
                     Condition::CalculatedValue(CalculatedValue::new(
-                        StorageMethod::Variable("var2"),
+                        StorageMethod::Variable("var2".into()),
                         vec![]
                     ))
                 ])),
@@ -786,16 +1822,16 @@ mod tests {
                 .next()
                 .unwrap();
 
-            let condition = super::parse_condition(condition);
+            let condition = super::parse_condition(condition, &source(tpl));
             assert_eq!(
                 condition,
                 Condition::And(AndCondition::new(vec![
                     Condition::CalculatedValue(CalculatedValue::new(
-                        StorageMethod::Variable("var1"),
+                        StorageMethod::Variable("var1".into()),
                         vec![]
                     )),
                     Condition::CalculatedValue(CalculatedValue::new(
-                        StorageMethod::Variable("var2"),
+                        StorageMethod::Variable("var2".into()),
                         vec![]
                     ))
                 ])),
@@ -810,22 +1846,22 @@ mod tests {
                 .next()
                 .unwrap();
 
-            let condition = super::parse_condition(condition);
+            let condition = super::parse_condition(condition, &source(tpl));
             assert_eq!(
                 condition,
                 Condition::And(AndCondition::new(vec![
                     Condition::Or(OrCondition::new(vec![
                         Condition::CalculatedValue(CalculatedValue::new(
-                            StorageMethod::Variable("var1"),
+                            StorageMethod::Variable("var1".into()),
                             vec![]
                         )),
                         Condition::CalculatedValue(CalculatedValue::new(
-                            StorageMethod::Variable("var2"),
+                            StorageMethod::Variable("var2".into()),
                             vec![]
                         ))
                     ])),
                     Condition::CalculatedValue(CalculatedValue::new(
-                        StorageMethod::Variable("var3"),
+                        StorageMethod::Variable("var3".into()),
                         vec![]
                     ))
                 ]))
@@ -840,21 +1876,21 @@ mod tests {
                 .next()
                 .unwrap();
 
-            let condition = super::parse_condition(condition);
+            let condition = super::parse_condition(condition, &source(tpl));
             assert_eq!(
                 condition,
                 Condition::or(vec![
                     Condition::CalculatedValue(CalculatedValue::new(
-                        StorageMethod::Variable("var1"),
+                        StorageMethod::Variable("var1".into()),
                         vec![]
                     )),
                     Condition::and(vec![
                         Condition::CalculatedValue(CalculatedValue::new(
-                            StorageMethod::Variable("var2"),
+                            StorageMethod::Variable("var2".into()),
                             vec![]
                         )),
                         Condition::CalculatedValue(CalculatedValue::new(
-                            StorageMethod::Variable("var3"),
+                            StorageMethod::Variable("var3".into()),
                             vec![]
                         ))
                     ])
@@ -880,11 +1916,11 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let assign = parse_assign(assign);
+            let assign = parse_assign(assign, &super::source(tpl));
             assert_eq!(
                 assign,
                 Assign::new(
-                    "my_var",
+                    "my_var".into(),
                     CalculatedValue::new(StorageMethod::Const(Value::Number(12.)), vec![])
                 )
             )
@@ -910,19 +1946,112 @@ mod tests {
                 .unwrap()
                 .next()
                 .unwrap();
-            let l = crate::parser::parse_loop(l).unwrap();
+            let l = crate::parser::parse_loop(l, &super::source(template)).unwrap();
             assert_eq!(
                 l,
                 Loop::new(
                     Condition::Compare(CompareCondition {
-                        left: CalculatedValue::new(StorageMethod::Variable("var"), vec![]),
+                        left: CalculatedValue::new(StorageMethod::Variable("var".into()), vec![]),
                         operator: CompareOperator::EQ,
                         right: CalculatedValue::new(
                             StorageMethod::Const(Value::Number(0.)),
                             vec![]
                         )
                     }),
-                    vec![Statement::Literal("Foo")]
+                    vec![Statement::Literal("Foo".into())]
+                )
+            )
+        }
+    }
+
+    #[cfg(feature = "include")]
+    mod include {
+        use crate::{
+            parser::{Parser, Rule, TemplateParser},
+            template::{Assign, CalculatedValue, Include, StorageMethod},
+            value::Value,
+        };
+
+        #[test]
+        fn parse_include() {
+            let template = r#"{include "row" with item=x|upper n=1}"#;
+
+            let i = TemplateParser::parse(Rule::include, template)
+                .unwrap()
+                .next()
+                .unwrap();
+            let i = crate::parser::parse_include(i, &super::source(template));
+            assert_eq!(
+                i,
+                Include::new(
+                    StorageMethod::Const(Value::String("row".to_owned())),
+                    vec![
+                        Assign::new(
+                            "item".into(),
+                            CalculatedValue::new(
+                                StorageMethod::Variable("x".into()),
+                                vec![("upper".into(), vec![])]
+                            )
+                        ),
+                        Assign::new(
+                            "n".into(),
+                            CalculatedValue::new(StorageMethod::Const(Value::Number(1.)), vec![])
+                        )
+                    ]
+                )
+            )
+        }
+    }
+
+    #[cfg(feature = "foreach")]
+    mod foreach {
+        use crate::{
+            parser::{Parser, Rule, TemplateParser},
+            template::{CalculatedValue, Foreach, Statement, StorageMethod},
+        };
+
+        #[test]
+        fn parse_foreach() {
+            let template = "{foreach item in items|upper}{item}{endforeach}";
+
+            let f = TemplateParser::parse(Rule::foreach_loop, template)
+                .unwrap()
+                .next()
+                .unwrap();
+            let f = crate::parser::parse_foreach(f, &super::source(template)).unwrap();
+            assert_eq!(
+                f,
+                Foreach::new(
+                    "item".into(),
+                    CalculatedValue::new(
+                        StorageMethod::Variable("items".into()),
+                        vec![("upper".into(), vec![])]
+                    ),
+                    vec![Statement::Calculated(CalculatedValue::new(
+                        StorageMethod::Variable("item".into()),
+                        vec![]
+                    ))],
+                    None
+                )
+            )
+        }
+
+        #[test]
+        fn parse_foreach_else() {
+            let template = "{foreach item in items}Foo{else}Bar{endforeach}";
+
+            let f = TemplateParser::parse(Rule::foreach_loop, template)
+                .unwrap()
+                .next()
+                .unwrap();
+            let f = crate::parser::parse_foreach(f, &super::source(template)).unwrap();
+            assert_eq!(
+                f,
+                Foreach::new(
+                    "item".into(),
+                    CalculatedValue::new(StorageMethod::Variable("items".into()), vec![]),
+                    vec![Statement::Literal("Foo".into())],
+                    Some(vec![Statement::Literal("Bar".into())])
                 )
             )
         }
@@ -965,6 +2094,15 @@ mod pest_tests {
         test_cases(&IDENTIFIER_CASES, Rule::identifier)
     }
 
+    #[test]
+    fn variable() {
+        test_cases(&IDENTIFIER_CASES, Rule::variable);
+        test_cases(
+            &["customer.address.city", "items[0].name", "matrix[1][2]", "map.Key_1"],
+            Rule::variable,
+        )
+    }
+
     #[test]
     fn argument() {
         test_cases!(NUMBER_CASES, number_cases, ":{}");
@@ -985,11 +2123,35 @@ mod pest_tests {
         test_cases(&cases, Rule::string)
     }
 
+    #[test]
+    fn array() {
+        test_cases(
+            &["[]", "[1]", r#"[1, "two", true]"#, "[[1, 2], [3]]"],
+            Rule::array,
+        )
+    }
+
     #[test]
     fn string_before_modifier() {
         test_cases(&[r#"{"test"|modifier:arg}"#], Rule::calculated)
     }
 
+    #[test]
+    fn operators() {
+        test_cases(
+            &[
+                "{a + b}",
+                "{a+b*c}",
+                "{(a + b) * c}",
+                "{ -a}",
+                "{- (a % 2)}",
+                r#"{first ~ " " ~ last|upper}"#,
+                "{(a / b)|round:2}",
+            ],
+            Rule::calculated,
+        )
+    }
+
     #[test]
     fn test_condition() {
         test_cases(
@@ -1002,6 +2164,9 @@ mod pest_tests {
                 "var1 == var2 || (var5 == var5)",
                 "var1 == var2 || var5 == var5 && var1 == \"foo\"",
                 "var1 == var2 || (var5 == var5 && var1 == \"foo\")",
+                "(a + b) * 2 > c",
+                "(a + b)",
+                "a % 2 == 0 && (b)",
             ],
             Rule::condition,
         );
@@ -1015,6 +2180,9 @@ mod pest_tests {
                 "{if i < 10}HI{else}TEST{endif}",
                 "{if i < 10}HI{else}{if i < 10}HI{else}TEST{endif}{endif}",
                 "{if i}HI{endif}",
+                "{if i}HI{elseif j}HO{endif}",
+                "{if i}HI{elif j}HO{elseif k}HU{else}TEST{endif}",
+                "{ if i } HI { elseif j } HO { endif }",
             ],
             Rule::conditional,
         );
@@ -1050,6 +2218,22 @@ mod pest_tests {
         )
     }
 
+    #[test]
+    fn test_comment() {
+        test_cases(
+            &["{* note *}", "{**}", "{* {var} {if a} * } *}", "{*\nmulti\nline\n*}"],
+            Rule::comment,
+        )
+    }
+
+    #[test]
+    fn test_literal_block() {
+        test_cases(
+            &["{literal}{/literal}", "{literal}{a} }{{/literal}", "{-literal-} {if} {-/literal-}"],
+            Rule::literal_block,
+        )
+    }
+
     #[test]
     fn test_assign() {
         test_cases(
@@ -1070,6 +2254,50 @@ mod pest_tests {
         )
     }
 
+    #[test]
+    fn test_foreach() {
+        test_cases(
+            &[
+                "{foreach item in items}{item}{endforeach}",
+                "{ foreach item in items|modifier } 1 { endforeach }",
+                "{foreach item in items}1{else}2{endforeach}",
+                "{foreach index in indexes}\n{index}\n{endforeach}",
+            ],
+            Rule::foreach_loop,
+        )
+    }
+
+    #[test]
+    fn identifier_starting_with_key_word() {
+        test_cases(&["input", "iffy", "elsewhere", "trueish"], Rule::identifier)
+    }
+
+    #[test]
+    fn test_include() {
+        test_cases(
+            &[
+                r#"{include "footer"}"#,
+                r#"{ include "row" with item=x }"#,
+                r#"{include name with item=x|upper other="b"}"#,
+            ],
+            Rule::include,
+        )
+    }
+
+    #[test]
+    fn test_inheritance() {
+        test_cases(&[r#"{extends "base"}"#, "{ extends layout }"], Rule::extends);
+        test_cases(
+            &[
+                "{block title}Title{endblock}",
+                "{ block title }{parent} Title{ endblock }",
+                "{block outer}{block inner}{endblock}{endblock}",
+            ],
+            Rule::block,
+        );
+        test_cases(&["{parent}", "{ parent }"], Rule::parent_block);
+    }
+
     fn test_cases(cases: &[&str], rule: Rule) {
         cases.iter().for_each(|input| {
             let parsed = TemplateParser::parse(rule, input);
@@ -1089,22 +2317,32 @@ mod legacy_tests {
 
     #[test]
     fn simple_compile() {
-        let tpl = parse("Simple template string".to_owned()).unwrap();
+        let tpl = parse("Simple template string".to_owned(), ParseOptions::default()).unwrap();
         assert_eq!(
-            vec![Statement::Literal("Simple template string" as *const _)],
+            vec![Statement::Literal("Simple template string".into())],
             tpl.tpl
         );
     }
 
     #[test]
     fn variable_value() {
-        let tpl = parse("Simple more {var} template {foo}".to_owned()).unwrap();
+        let tpl = parse(
+            "Simple more {var} template {foo}".to_owned(),
+            ParseOptions::default(),
+        )
+        .unwrap();
         assert_eq!(
             vec![
-                Statement::Literal("Simple more " as *const _),
-                Statement::Calculated(CalculatedValue::new(StorageMethod::Variable("var"), vec![])),
-                Statement::Literal(" template " as *const _),
-                Statement::Calculated(CalculatedValue::new(StorageMethod::Variable("foo"), vec![]))
+                Statement::Literal("Simple more ".into()),
+                Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Variable("var".into()),
+                    vec![]
+                )),
+                Statement::Literal(" template ".into()),
+                Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Variable("foo".into()),
+                    vec![]
+                ))
             ],
             tpl.tpl
         )
@@ -1112,15 +2350,15 @@ mod legacy_tests {
 
     #[test]
     fn variable_value_simple_modifier() {
-        let tpl = parse("Simple {var|test} template".to_owned()).unwrap();
+        let tpl = parse("Simple {var|test} template".to_owned(), ParseOptions::default()).unwrap();
         assert_eq!(
             vec![
-                Statement::Literal("Simple " as *const _),
+                Statement::Literal("Simple ".into()),
                 Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
-                    vec![("test" as *const _, vec![])]
+                    StorageMethod::Variable("var".into()),
+                    vec![("test".into(), vec![])]
                 )),
-                Statement::Literal(" template" as *const _)
+                Statement::Literal(" template".into())
             ],
             tpl.tpl
         );
@@ -1128,20 +2366,24 @@ mod legacy_tests {
 
     #[test]
     fn variable_value_modifier_string_value() {
-        let tpl = parse(r#"Simple {var|test:"test value"} template"#.to_owned()).unwrap();
+        let tpl = parse(
+            r#"Simple {var|test:"test value"} template"#.to_owned(),
+            ParseOptions::default(),
+        )
+        .unwrap();
         assert_eq!(
             vec![
-                Statement::Literal("Simple " as *const _),
+                Statement::Literal("Simple ".into()),
                 Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
+                    StorageMethod::Variable("var".into()),
                     vec![(
-                        "test" as *const _,
+                        "test".into(),
                         vec![StorageMethod::Const(Value::String(
                             "test value".to_string()
                         ))]
                     )]
                 )),
-                Statement::Literal(" template" as *const _)
+                Statement::Literal(" template".into())
             ],
             tpl.tpl
         );
@@ -1149,18 +2391,22 @@ mod legacy_tests {
 
     #[test]
     fn variable_value_modifier_num_value() {
-        let tpl = parse(r#"Simple {var|test:42} template"#.to_owned()).unwrap();
+        let tpl = parse(
+            r#"Simple {var|test:42} template"#.to_owned(),
+            ParseOptions::default(),
+        )
+        .unwrap();
         assert_eq!(
             vec![
-                Statement::Literal("Simple " as *const _),
+                Statement::Literal("Simple ".into()),
                 Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
+                    StorageMethod::Variable("var".into()),
                     vec![(
-                        "test" as *const _,
+                        "test".into(),
                         vec![StorageMethod::Const(Value::Number(42_f64))]
                     )]
                 )),
-                Statement::Literal(" template" as *const _)
+                Statement::Literal(" template".into())
             ],
             tpl.tpl
         );
@@ -1168,15 +2414,19 @@ mod legacy_tests {
 
     #[test]
     fn variable_value_modifier_var_value() {
-        let tpl = parse(r#"Simple {var|test:foobar} template"#.to_owned()).unwrap();
+        let tpl = parse(
+            r#"Simple {var|test:foobar} template"#.to_owned(),
+            ParseOptions::default(),
+        )
+        .unwrap();
         assert_eq!(
             vec![
-                Statement::Literal("Simple " as *const _),
+                Statement::Literal("Simple ".into()),
                 Statement::Calculated(CalculatedValue::new(
-                    StorageMethod::Variable("var"),
-                    vec![("test" as *const _, vec![StorageMethod::Variable("foobar")])]
+                    StorageMethod::Variable("var".into()),
+                    vec![("test".into(), vec![StorageMethod::Variable("foobar".into())])]
                 )),
-                Statement::Literal(" template" as *const _)
+                Statement::Literal(" template".into())
             ],
             tpl.tpl
         );
@@ -1,30 +1,165 @@
-use std::collections::HashMap;
-
-use crate::variable_container::VariableContainer;
+use std::{borrow::Cow, collections::HashMap};
+
+#[cfg(feature = "inheritance")]
+use crate::template::Block;
+use crate::{
+    error::{Error, ErrorKind, WriteError},
+    escape::Escape,
+    template::Template,
+    undefined::Undefined,
+    value::Value,
+    variable_container::{Scopes, VariableContainer},
+};
 
 use super::modifier::Modifier;
 
 pub struct RenderContext<'a, VC: VariableContainer> {
     pub modifier: &'a HashMap<&'static str, &'a Modifier>,
-    pub variables: VC,
+    /// The rendered data below the variables assigned by the template
+    pub variables: Scopes<VC>,
+    /// Escaping applied to printed values
+    pub escape: Escape,
+    /// Handling of variables missing from `variables`
+    pub undefined: Undefined,
+    pub templates: Option<&'a dyn TemplateStore>,
+    /// Templates that are currently being rendered. Used to detect include cycles.
+    pub template_stack: Vec<&'a Template>,
+    /// Templates whose blocks replace the blocks of the template currently rendered.
+    /// The most derived template comes first.
+    #[cfg(feature = "inheritance")]
+    pub block_overrides: Vec<&'a Template>,
+    /// Blocks `{parent}` refers to for every block that is currently being rendered.
+    /// Blocks of overriding templates are stored with their template.
+    #[cfg(feature = "inheritance")]
+    pub block_parents: Vec<Vec<(Option<&'a Template>, &'a Block)>>,
 }
 
 impl<'a, VC: VariableContainer> RenderContext<'a, VC> {
     pub fn new(modifier: &'a HashMap<&'static str, &'a Modifier>, variables: VC) -> Self {
         Self {
             modifier,
-            variables,
+            variables: Scopes::new(variables),
+            escape: Escape::None,
+            undefined: Undefined::Error,
+            templates: None,
+            template_stack: Vec::new(),
+            #[cfg(feature = "inheritance")]
+            block_overrides: Vec::new(),
+            #[cfg(feature = "inheritance")]
+            block_parents: Vec::new(),
         }
     }
 }
 
+/// Gives access to other templates while rendering.
+#[cfg_attr(not(any(feature = "include", feature = "inheritance")), allow(dead_code))]
+pub trait TemplateStore {
+    fn get(&self, key: &Value) -> Option<&Template>;
+}
+
+impl TemplateStore for HashMap<String, Template> {
+    fn get(&self, key: &Value) -> Option<&Template> {
+        self.get(&*template_name(key)?)
+    }
+}
+
+/// Templates of a [`crate::MiniTemplate`].
+///
+/// Templates keep their slot when they are replaced, so the keys of a `MiniTemplate` can refer
+/// to them by index without being cloned.
+#[derive(Default)]
+pub(crate) struct TemplateRegistry {
+    templates: Vec<Template>,
+    /// Slots of the templates added with a name
+    names: HashMap<String, usize>,
+}
+
+impl TemplateRegistry {
+    /// Adds a template and returns its slot.
+    pub fn push(&mut self, template: Template) -> usize {
+        let slot = self.templates.len();
+        if let Some(name) = &template.name {
+            self.names.insert(name.clone(), slot);
+        }
+        self.templates.push(template);
+        slot
+    }
+
+    /// Replaces the template in `slot` and returns the old one.
+    pub fn replace(&mut self, slot: usize, template: Template) -> Template {
+        if let Some(name) = &template.name {
+            self.names.insert(name.clone(), slot);
+        }
+        let old = std::mem::replace(&mut self.templates[slot], template);
+        if let Some(old_name) = &old.name {
+            if old.name != self.templates[slot].name && self.names.get(old_name) == Some(&slot) {
+                self.names.remove(old_name);
+            }
+        }
+        old
+    }
+
+    pub fn get(&self, slot: usize) -> &Template {
+        &self.templates[slot]
+    }
+
+}
+
+impl TemplateStore for TemplateRegistry {
+    fn get(&self, key: &Value) -> Option<&Template> {
+        let slot = self.names.get(&*template_name(key)?)?;
+        Some(&self.templates[*slot])
+    }
+}
+
+/// Returns the name a template value like `"footer"` or `3` refers to.
+fn template_name(key: &Value) -> Option<Cow<'_, str>> {
+    match key {
+        Value::String(name) => Some(Cow::Borrowed(name)),
+        Value::Integer(i) => Some(Cow::Owned(i.to_string())),
+        _ => None,
+    }
+}
+
+/// Adapts a [`std::io::Write`] to [`std::fmt::Write`] and keeps the io error
+/// because [`std::fmt::Error`] can not carry it.
+pub(crate) struct IoWriter<'w, W: std::io::Write> {
+    inner: &'w mut W,
+    error: Option<std::io::Error>,
+}
+
+impl<'w, W: std::io::Write> IoWriter<'w, W> {
+    pub(crate) fn new(inner: &'w mut W) -> Self {
+        Self { inner, error: None }
+    }
+
+    /// Replaces a formatter error with the io error that caused it.
+    pub(crate) fn take_error<'t>(&mut self, error: Error<'t>) -> Error<'t> {
+        let kind = match (error.kind, self.error.take()) {
+            (ErrorKind::Write(WriteError::Fmt), Some(e)) => ErrorKind::Write(WriteError::Io(e)),
+            (kind, _) => kind,
+        };
+        Error { kind, ..error }
+    }
+}
+
+impl<W: std::io::Write> std::fmt::Write for IoWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            std::fmt::Error
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
     use mini_template_macro::create_modifier;
     use crate::{
-        modifier::Modifier, parser::parse, renderer::RenderContext,
-        template::Render, value::Value,
+        error::{Error, ErrorKind}, escape::Escape, modifier::Modifier,
+        parser::{parse, ParseOptions}, renderer::RenderContext, template::Render,
+        undefined::{Undefined, Unknown}, value::Value,
     };
 
     #[create_modifier]
@@ -40,20 +175,20 @@ mod tests {
     #[test]
     fn literal() {
         let tpl = String::from("Simple template string");
-        let tpl = parse(tpl).unwrap();
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
         let mut rendered = String::new();
         tpl.render(
             &mut RenderContext::new(&HashMap::new(), HashMap::new()),
             &mut rendered,
         )
         .unwrap();
-        assert_eq!(rendered, tpl.tpl_str);
+        assert_eq!(rendered, &*tpl.tpl_str);
     }
 
     #[test]
     fn replace_variables() {
         let tpl = String::from("Simple {foo} template string");
-        let tpl = parse(tpl).unwrap();
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
         let mut variables = HashMap::new();
         variables.insert("foo".to_owned(), Value::String("my test value".to_owned()));
         let mut rendered = String::new();
@@ -69,10 +204,102 @@ mod tests {
         );
     }
 
+    #[test]
+    fn array_and_map_variables() {
+        let tpl = String::from("{list} {map}");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+        let mut variables = HashMap::new();
+        variables.insert(
+            "list".to_owned(),
+            Value::Array(vec![Value::Number(1.), Value::String("a".to_owned())]),
+        );
+        variables.insert(
+            "map".to_owned(),
+            Value::Map(BTreeMap::from_iter([
+                ("b".to_owned(), Value::Bool(true)),
+                ("a".to_owned(), Value::Number(2.)),
+            ])),
+        );
+        let mut rendered = String::new();
+
+        tpl.render(
+            &mut RenderContext::new(&HashMap::new(), variables),
+            &mut rendered,
+        )
+        .unwrap();
+        assert_eq!(rendered, String::from("[1, a] {a: 2, b: true}"));
+    }
+
+    #[test]
+    fn nested_path() {
+        let tpl = String::from("{customer.address.city} {items[1].name}");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+        let mut variables = HashMap::new();
+        variables.insert(
+            "customer".to_owned(),
+            Value::Map(BTreeMap::from_iter([(
+                "address".to_owned(),
+                Value::Map(BTreeMap::from_iter([(
+                    "city".to_owned(),
+                    Value::String("Berlin".to_owned()),
+                )])),
+            )])),
+        );
+        variables.insert(
+            "items".to_owned(),
+            Value::Array(vec![
+                Value::Map(BTreeMap::from_iter([(
+                    "name".to_owned(),
+                    Value::String("first".to_owned()),
+                )])),
+                Value::Map(BTreeMap::from_iter([(
+                    "name".to_owned(),
+                    Value::String("second".to_owned()),
+                )])),
+            ]),
+        );
+        let mut rendered = String::new();
+
+        tpl.render(
+            &mut RenderContext::new(&HashMap::new(), variables),
+            &mut rendered,
+        )
+        .unwrap();
+        assert_eq!(rendered, String::from("Berlin second"));
+    }
+
+    #[test]
+    fn unknown_path_segment() {
+        let tpl = String::from("{customer.address.city}");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+        let mut variables = HashMap::new();
+        variables.insert(
+            "customer".to_owned(),
+            Value::Map(BTreeMap::from_iter([(
+                "name".to_owned(),
+                Value::String("Foo".to_owned()),
+            )])),
+        );
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        let mut rendered = String::new();
+
+        let result = tpl.render(
+            &mut RenderContext::new(&modifiers, variables),
+            &mut rendered,
+        );
+        assert_eq!(
+            result,
+            Err(Error::new(
+                ErrorKind::UnknownVariable("customer.address"),
+                Some(&"customer.address".into())
+            ))
+        );
+    }
+
     #[test]
     fn modifier() {
         let tpl = String::from("Simple {foo|upper} template string");
-        let tpl = parse(tpl).unwrap();
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
 
         let mut variables = HashMap::new();
         variables.insert("foo".to_owned(), Value::String("my test value".to_owned()));
@@ -95,7 +322,7 @@ mod tests {
     #[test]
     fn modifier_values() {
         let tpl = String::from(r#"Simple {foo|args:"BAR":42} template string"#);
-        let tpl = parse(tpl).unwrap();
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
 
         let mut variables = HashMap::new();
         variables.insert("foo".to_owned(), Value::String("my test value".to_owned()));
@@ -118,7 +345,7 @@ mod tests {
     #[test]
     fn modifier_list() {
         let tpl = String::from(r#"Simple {foo|upper|args:"bar":42} template string"#);
-        let tpl = parse(tpl).unwrap();
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
 
         let mut variables = HashMap::new();
         variables.insert("foo".to_owned(), Value::String("my test value".to_owned()));
@@ -139,6 +366,320 @@ mod tests {
         );
     }
 
+    #[test]
+    fn escape_values() {
+        let tpl = String::from("<p>{foo}</p><p>{foo|upper}</p><p>{foo|raw}</p>");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let mut modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        modifiers.insert("upper", &upper_case_modifier);
+
+        let mut variables = HashMap::new();
+        variables.insert("foo".to_owned(), Value::String("<b>'x'</b>".to_owned()));
+        let mut context = RenderContext::new(&modifiers, variables);
+        context.escape = Escape::Html;
+
+        let mut rendered = String::new();
+        tpl.render(&mut context, &mut rendered).unwrap();
+        assert_eq!(
+            rendered,
+            String::from(
+                "<p>&lt;b&gt;&#39;x&#39;&lt;/b&gt;</p><p>&lt;B&gt;&#39;X&#39;&lt;/B&gt;</p><p><b>'x'</b></p>"
+            )
+        );
+    }
+
+    #[test]
+    fn raw_is_not_last_modifier() {
+        let tpl = String::from("{foo|raw|upper}");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let mut modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        modifiers.insert("upper", &upper_case_modifier);
+
+        let mut variables = HashMap::new();
+        variables.insert("foo".to_owned(), Value::String("<b>".to_owned()));
+        let mut context = RenderContext::new(&modifiers, variables);
+        context.escape = Escape::Xml;
+
+        let mut rendered = String::new();
+        tpl.render(&mut context, &mut rendered).unwrap();
+        assert_eq!(rendered, String::from("&lt;B&gt;"));
+    }
+
+    #[test]
+    fn default_modifier() {
+        let tpl = String::from(
+            r#"{name|default:"n/a"} {none|default:"n/a"} {user.mail|default:fallback}"#,
+        );
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let mut variables = HashMap::new();
+        variables.insert("name".to_owned(), Value::String("Foo".to_owned()));
+        variables.insert("none".to_owned(), Value::Null);
+        variables.insert("fallback".to_owned(), Value::String("-".to_owned()));
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+
+        let mut rendered = String::new();
+        tpl.render(&mut RenderContext::new(&modifiers, variables), &mut rendered)
+            .unwrap();
+        assert_eq!(rendered, String::from("Foo n/a -"));
+    }
+
+    #[test]
+    fn is_defined_and_is_null() {
+        let tpl = String::from(
+            "{a|is_defined} {a|is_null} {b|is_defined} {b|is_null} {c|is_defined}",
+        );
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let mut variables = HashMap::new();
+        variables.insert("a".to_owned(), Value::Null);
+        variables.insert("b".to_owned(), Value::Number(0.));
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+
+        let mut rendered = String::new();
+        tpl.render(&mut RenderContext::new(&modifiers, variables), &mut rendered)
+            .unwrap();
+        assert_eq!(rendered, String::from("true true true false false"));
+    }
+
+    #[test]
+    fn undefined_as_null() {
+        let tpl = String::from("[{missing}] [{missing.key}] [{missing|is_null}]");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+
+        let mut context = RenderContext::new(&modifiers, HashMap::new());
+        let mut rendered = String::new();
+        assert_eq!(
+            tpl.render(&mut context, &mut rendered),
+            Err(Error::new(ErrorKind::UnknownVariable("missing"), Some(&"missing".into())))
+        );
+
+        context.undefined = Undefined::Null;
+        let mut rendered = String::new();
+        tpl.render(&mut context, &mut rendered).unwrap();
+        assert_eq!(rendered, String::from("[] [] [true]"));
+    }
+
+    #[test]
+    fn undefined_policies() {
+        let tpl = String::from(r#"[{name|shout}] [{missing|upper}] [{name|upper:missing:"x"}]"#);
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let mut modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        modifiers.insert("upper", &upper_case_modifier);
+        let mut variables = HashMap::new();
+        variables.insert("name".to_owned(), Value::String("foo".to_owned()));
+        let mut context = RenderContext::new(&modifiers, variables);
+        let mut render = |undefined| {
+            context.undefined = undefined;
+            let mut rendered = String::new();
+            tpl.render(&mut context, &mut rendered).map(|_| rendered)
+        };
+
+        assert_eq!(
+            render(Undefined::Error),
+            Err(Error::new(ErrorKind::UnknownModifier("shout"), Some(&"shout".into())))
+        );
+        assert_eq!(render(Undefined::Empty), Ok(String::from("[] [] [FOO]")));
+        assert_eq!(
+            render(Undefined::Keep),
+            Ok(String::from(r#"[{name|shout}] [{missing|upper}] [{name|upper:missing:"x"}]"#))
+        );
+    }
+
+    #[test]
+    fn operators() {
+        let tpl = String::from(r#"{a + b * 2} {(a + b) * 2} {b / 2} { -b % 4} {a ~ "-" ~ b}"#);
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        let mut variables = HashMap::new();
+        variables.insert("a".to_owned(), Value::Integer(1));
+        variables.insert("b".to_owned(), Value::Integer(5));
+
+        let mut rendered = String::new();
+        tpl.render(&mut RenderContext::new(&modifiers, variables), &mut rendered)
+            .unwrap();
+        assert_eq!(rendered, String::from("11 12 2.5 -1 1-5"));
+    }
+
+    #[test]
+    fn negation_after_trim_marker() {
+        let tpl = String::from("[{ -x}] [{(-x)}] [ {- (x)}]");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        let mut variables = HashMap::new();
+        variables.insert("x".to_owned(), Value::Integer(5));
+        let mut rendered = String::new();
+        tpl.render(&mut RenderContext::new(&modifiers, variables), &mut rendered)
+            .unwrap();
+        assert_eq!(rendered, String::from("[-5] [-5] [5]"));
+    }
+
+    #[test]
+    fn operator_errors() {
+        let tpl = parse(String::from("{a / 0}"), ParseOptions::default()).unwrap();
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        let mut variables = HashMap::new();
+        variables.insert("a".to_owned(), Value::Integer(1));
+        let mut rendered = String::new();
+        let error = tpl
+            .render(&mut RenderContext::new(&modifiers, variables), &mut rendered)
+            .unwrap_err();
+        assert!(matches!(error.kind, ErrorKind::Modifier(_)));
+        assert_eq!(error.span.as_deref(), Some("/"));
+    }
+
+    #[test]
+    fn operator_keeps_tag() {
+        let tpl = parse(String::from("{a + missing * 2}"), ParseOptions::default()).unwrap();
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        let mut variables = HashMap::new();
+        variables.insert("a".to_owned(), Value::Integer(1));
+        let mut context = RenderContext::new(&modifiers, variables);
+        context.undefined = Undefined::Keep;
+        let mut rendered = String::new();
+        tpl.render(&mut context, &mut rendered).unwrap();
+        assert_eq!(rendered, String::from("{a + missing * 2}"));
+    }
+
+    #[cfg(feature = "conditional")]
+    #[test]
+    fn operator_condition() {
+        let tpl = String::from("{if (a + 1) * 2 > 5 && a % 2 == 0}even{else}odd{endif}");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        let render = |a| {
+            let mut variables = HashMap::new();
+            variables.insert("a".to_owned(), Value::Integer(a));
+            let mut rendered = String::new();
+            tpl.render(&mut RenderContext::new(&modifiers, variables), &mut rendered)
+                .unwrap();
+            rendered
+        };
+        assert_eq!(render(4), "even");
+        assert_eq!(render(3), "odd");
+    }
+
+    #[cfg(feature = "assign")]
+    #[test]
+    fn operator_assign() {
+        let tpl = parse(String::from("{n = n * 2 + 1}{n}"), ParseOptions::default()).unwrap();
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        let mut variables = HashMap::new();
+        variables.insert("n".to_owned(), Value::Integer(3));
+        let mut rendered = String::new();
+        tpl.render(&mut RenderContext::new(&modifiers, variables), &mut rendered)
+            .unwrap();
+        assert_eq!(rendered, String::from("7"));
+    }
+
+    #[cfg(all(feature = "conditional", feature = "foreach"))]
+    #[test]
+    fn undefined_policies_in_expressions() {
+        let tpl = String::from(
+            "[{missing|upper}] [{missing + 1}] [{ -missing}] [{if missing}yes{else}no{endif}] \
+            [{foreach i in missing}{i}{else}none{endforeach}] [{if missing == 1}yes{endif}]",
+        );
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let mut modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        modifiers.insert("upper", &upper_case_modifier);
+        let mut context = RenderContext::new(&modifiers, HashMap::new());
+        let mut render = |undefined| {
+            context.undefined = undefined;
+            let mut rendered = String::new();
+            tpl.render(&mut context, &mut rendered).map(|_| rendered)
+        };
+
+        assert_eq!(
+            render(Undefined::Error),
+            Err(Error::new(ErrorKind::UnknownVariable("missing"), Some(&"missing".into())))
+        );
+        assert_eq!(render(Undefined::Null), Ok(String::from("[] [] [] [no] [none] []")));
+        assert_eq!(render(Undefined::Empty), Ok(String::from("[] [] [] [no] [none] []")));
+        assert_eq!(
+            render(Undefined::Keep),
+            Ok(String::from("[{missing|upper}] [{missing + 1}] [{ -missing}] [no] [none] []"))
+        );
+    }
+
+    #[test]
+    fn undefined_unknown_modifier_skips_operators() {
+        let tpl = parse(String::from("[{name|shout ~ \"!\"}]"), ParseOptions::default()).unwrap();
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        let mut variables = HashMap::new();
+        variables.insert("name".to_owned(), Value::String("foo".to_owned()));
+        let mut context = RenderContext::new(&modifiers, variables);
+        context.undefined = Undefined::Null;
+        let mut rendered = String::new();
+        tpl.render(&mut context, &mut rendered).unwrap();
+        assert_eq!(rendered, String::from("[]"));
+    }
+
+    #[test]
+    fn default_after_expression() {
+        let tpl = String::from("{(missing + 1)|default:0} {(missing ~ \"x\")|is_defined}");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        let mut context = RenderContext::new(&modifiers, HashMap::new());
+        for undefined in [Undefined::Error, Undefined::Null, Undefined::Keep] {
+            context.undefined = undefined;
+            let mut rendered = String::new();
+            tpl.render(&mut context, &mut rendered).unwrap();
+            assert_eq!(rendered, String::from("0 false"));
+        }
+    }
+
+    #[test]
+    fn default_after_other_modifiers() {
+        let tpl = String::from(concat!(
+            r#"{missing|upper|default:"x"} {name|upper|default:"x"} "#,
+            r#"{(missing ~ "a")|upper|is_null}"#
+        ));
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+        let mut modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        modifiers.insert("upper", &upper_case_modifier);
+        let mut variables = HashMap::new();
+        variables.insert("name".to_owned(), Value::String("Foo".to_owned()));
+        let mut rendered = String::new();
+        tpl.render(&mut RenderContext::new(&modifiers, variables), &mut rendered)
+            .unwrap();
+        assert_eq!(rendered, String::from("x FOO true"));
+
+        let tpl = parse(String::from("{missing|shout|default:1}"), ParseOptions::default());
+        let mut rendered = String::new();
+        assert_eq!(
+            tpl.unwrap()
+                .render(&mut RenderContext::new(&modifiers, HashMap::new()), &mut rendered),
+            Err(Error::new(ErrorKind::UnknownModifier("shout"), Some(&"shout".into())))
+        );
+    }
+
+    #[test]
+    fn undefined_callback() {
+        let tpl = String::from("{missing|upper} {name|shout}");
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let mut modifiers: HashMap<&str, &Modifier> = HashMap::new();
+        modifiers.insert("upper", &upper_case_modifier);
+        let mut variables = HashMap::new();
+        variables.insert("name".to_owned(), Value::String("foo".to_owned()));
+        let mut context = RenderContext::new(&modifiers, variables);
+        context.undefined = Undefined::callback(|unknown| match unknown {
+            Unknown::Variable(name) => Some(Value::String(format!("${}", name))),
+            Unknown::Modifier(name) => Some(Value::String(format!("<{}>", name))),
+        });
+
+        let mut rendered = String::new();
+        tpl.render(&mut context, &mut rendered).unwrap();
+        assert_eq!(rendered, String::from("$MISSING <shout>"));
+    }
+
     #[test]
     fn condition1() {
         let tpl = String::from(
@@ -146,7 +687,7 @@ mod tests {
 {if var1}Bar {endif}
 Baz"#,
         );
-        let tpl = parse(tpl).unwrap();
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
 
         let mut variables = HashMap::new();
         variables.insert("var1".to_owned(), Value::Bool(true));
@@ -165,7 +706,7 @@ Baz"#,
     #[test]
     fn condition2() {
         let tpl = String::from("Foo\n{if var1}\nBar\n{endif}\nBaz");
-        let tpl = parse(tpl).unwrap();
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
 
         let mut variables = HashMap::new();
         variables.insert("var1".to_owned(), Value::Bool(true));
@@ -184,7 +725,7 @@ Baz"#,
     #[test]
     fn condition3() {
         let tpl = String::from("Foo{if var1}Bar{else}Fizz{endif}Baz");
-        let tpl = parse(tpl).unwrap();
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
 
         let mut variables = HashMap::new();
         variables.insert("var1".to_owned(), Value::Bool(true));
@@ -209,4 +750,47 @@ Baz"#,
         .unwrap();
         assert_eq!(rendered, String::from("FooFizzBaz"));
     }
+
+    #[cfg(all(feature = "foreach", feature = "conditional"))]
+    #[test]
+    fn foreach() {
+        let tpl = String::from(
+            "{foreach item in items}{item.name}{if loop.last}.{else}, {endif}{else}none{endforeach}",
+        );
+        let tpl = parse(tpl, ParseOptions::default()).unwrap();
+
+        let mut variables = HashMap::new();
+        variables.insert(
+            "items".to_owned(),
+            Value::Array(vec![
+                Value::Map(BTreeMap::from_iter([(
+                    "name".to_owned(),
+                    Value::String("foo".to_owned()),
+                )])),
+                Value::Map(BTreeMap::from_iter([(
+                    "name".to_owned(),
+                    Value::String("bar".to_owned()),
+                )])),
+            ]),
+        );
+        let modifiers: HashMap<&str, &Modifier> = HashMap::new();
+
+        let mut rendered = String::new();
+        tpl.render(
+            &mut RenderContext::new(&modifiers, variables),
+            &mut rendered,
+        )
+        .unwrap();
+        assert_eq!(rendered, String::from("foo, bar."));
+
+        let mut variables = HashMap::new();
+        variables.insert("items".to_owned(), Value::Array(vec![]));
+        let mut rendered = String::new();
+        tpl.render(
+            &mut RenderContext::new(&modifiers, variables),
+            &mut rendered,
+        )
+        .unwrap();
+        assert_eq!(rendered, String::from("none"));
+    }
 }
@@ -1,35 +1,56 @@
-use crate::{renderer::RenderContext, variable_container::VariableContainer};
+use crate::{renderer::RenderContext, value::Value, variable_container::VariableContainer};
 
-use super::CalculatedValue;
+use super::{CalculatedValue, Span, Substr};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Assign {
-    identifier: *const str,
+    identifier: Substr,
     calc: CalculatedValue,
+    span: Span,
 }
 
 impl Assign {
-    pub fn new(identifier: *const str, calc: CalculatedValue) -> Self {
-        Self { identifier, calc }
+    pub fn new(identifier: Substr, calc: CalculatedValue) -> Self {
+        Self {
+            identifier,
+            calc,
+            span: Span::default(),
+        }
     }
 
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// Updates the variable in the innermost scope containing it or sets it in the current scope.
     pub fn assign<VC: VariableContainer>(
         &self,
         context: &mut RenderContext<VC>,
-    ) -> crate::error::Result<()> {
-        let v = self.calc.calc(context)?;
-        // Safety: identifier points to the original template string
-        let k = unsafe { self.identifier.as_ref().unwrap() };
-        context.variables.set(k.to_owned(), v);
+    ) -> crate::error::Result<'_, ()> {
+        let v = self.value(context)?;
+        context.variables.set(self.identifier.to_string(), v);
         Ok(())
     }
-}
 
-impl PartialEq for Assign {
-    fn eq(&self, other: &Self) -> bool {
-        // Safety: identifier points to the original template string
-        let ident_eq = unsafe { self.identifier.as_ref() == other.identifier.as_ref() };
-        ident_eq && self.calc == other.calc
+    /// Sets the variable in the current scope without changing variables of outer scopes.
+    #[cfg(feature = "include")]
+    pub fn assign_local<VC: VariableContainer>(
+        &self,
+        context: &mut RenderContext<VC>,
+    ) -> crate::error::Result<'_, ()> {
+        let v = self.value(context)?;
+        context.variables.set_local(self.identifier.to_string(), v);
+        Ok(())
+    }
+
+    fn value<VC: VariableContainer>(
+        &self,
+        context: &RenderContext<VC>,
+    ) -> crate::error::Result<'_, Value> {
+        self.calc
+            .calc(context)
+            .map_err(|e| e.or_span(self.span.get()))
     }
 }
 
@@ -54,8 +75,8 @@ mod tests {
         let mut rc = RenderContext::new(&modifiers, vars);
 
         let assign = Assign::new(
-            "output",
-            CalculatedValue::new(StorageMethod::Variable("input"), vec![]),
+            "output".into(),
+            CalculatedValue::new(StorageMethod::Variable("input".into()), vec![]),
         );
         assert!(assign.assign(&mut rc).is_ok());
         assert_eq!(rc.variables.get("output"), Some(&Value::Number(42.)))
@@ -71,10 +92,10 @@ mod tests {
         let mut rc = RenderContext::new(&modifiers, vars);
 
         let assign = Assign::new(
-            "output",
+            "output".into(),
             CalculatedValue::new(
-                StorageMethod::Variable("input"),
-                vec![("add", vec![StorageMethod::Const(Value::Number(2.))])],
+                StorageMethod::Variable("input".into()),
+                vec![("add".into(), vec![StorageMethod::Const(Value::Number(2.))])],
             ),
         );
         assert!(assign.assign(&mut rc).is_ok());
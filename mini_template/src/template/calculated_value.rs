@@ -1,91 +1,374 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::ControlFlow};
 
-use crate::{renderer::RenderContext, value::Value, variable_container::VariableContainer};
+use crate::{
+    error::{Error, ErrorKind},
+    modifier,
+    renderer::RenderContext,
+    undefined::{Undefined, Unknown},
+    value::Value,
+    variable_container::VariableContainer,
+};
 
-use super::StorageMethod;
+use super::{Span, StorageMethod, Substr};
 
-#[derive(Debug)]
+/// Marks a value as trusted. It is never looked up as a registered modifier.
+const RAW_MODIFIER: &str = "raw";
+/// Replaces a null or undefined value with its argument.
+const DEFAULT_MODIFIER: &str = "default";
+/// Checks if the variable exists. Replaces the value with a boolean.
+const IS_DEFINED_MODIFIER: &str = "is_defined";
+/// Checks if the value is null or undefined. Replaces the value with a boolean.
+const IS_NULL_MODIFIER: &str = "is_null";
+/// Modifiers handled while evaluating. Registered modifiers with these names are never called.
+pub(crate) const BUILTIN_MODIFIERS: [&str; 4] =
+    [RAW_MODIFIER, DEFAULT_MODIFIER, IS_DEFINED_MODIFIER, IS_NULL_MODIFIER];
+/// Modifiers accepting undefined values
+const UNDEFINED_MODIFIERS: [&str; 3] = [DEFAULT_MODIFIER, IS_DEFINED_MODIFIER, IS_NULL_MODIFIER];
+
+/// Infix operators like `price * qty` or `first ~ " " ~ last`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    /// Joins the printed values
+    Concat,
+}
+
+impl Operator {
+    fn apply(self, left: &Value, right: &Value) -> modifier::error::Result<Value> {
+        match self {
+            Self::Add => modifier::add(left, vec![right]),
+            Self::Sub => modifier::sub(left, vec![right]),
+            Self::Mul => modifier::mul(left, vec![right]),
+            Self::Div => modifier::div(left, vec![right]),
+            Self::Rem => modifier::rem(left, vec![right]),
+            Self::Concat => Ok(Value::String(format!("{}{}", left, right))),
+        }
+    }
+}
+
+/// The value modifiers are applied to.
+#[derive(Debug, PartialEq)]
+enum Operand {
+    Value(StorageMethod),
+    /// `-value`. The span is the operator.
+    Negate(Box<CalculatedValue>, Span),
+    /// `left operator right`. The span is the operator.
+    Operation(Box<CalculatedValue>, Operator, Span, Box<CalculatedValue>),
+}
+
+#[derive(Debug, PartialEq)]
 pub struct CalculatedValue {
-    value: StorageMethod,
-    modifiers: Vec<(*const str, Vec<StorageMethod>)>,
+    value: Operand,
+    modifiers: Vec<(Substr, Vec<StorageMethod>)>,
+    span: Span,
+    /// The whole tag like `{name|upper}` if the value is printed
+    tag: Span,
 }
 
 impl CalculatedValue {
-    pub fn new(value: StorageMethod, modifiers: Vec<(*const str, Vec<StorageMethod>)>) -> Self {
-        Self { value, modifiers }
+    pub fn new(value: StorageMethod, modifiers: Vec<(Substr, Vec<StorageMethod>)>) -> Self {
+        Self::with_operand(Operand::Value(value), modifiers)
+    }
+
+    /// Creates `-value`. `operator` is the span of the minus sign.
+    pub fn negate(value: CalculatedValue, operator: Span) -> Self {
+        Self::with_operand(Operand::Negate(Box::new(value), operator), Vec::new())
+    }
+
+    /// Creates `left operator right`. `operator_span` is used for calculation errors.
+    pub fn operation(
+        left: CalculatedValue,
+        operator: Operator,
+        operator_span: Span,
+        right: CalculatedValue,
+    ) -> Self {
+        let operand = Operand::Operation(Box::new(left), operator, operator_span, Box::new(right));
+        Self::with_operand(operand, Vec::new())
+    }
+
+    fn with_operand(value: Operand, modifiers: Vec<(Substr, Vec<StorageMethod>)>) -> Self {
+        Self {
+            value,
+            modifiers,
+            span: Span::default(),
+            tag: Span::default(),
+        }
+    }
+
+    /// Applies more modifiers after the existing ones, e.g. for `(a ~ b)|upper`.
+    pub fn with_modifiers(mut self, modifiers: Vec<(Substr, Vec<StorageMethod>)>) -> Self {
+        self.modifiers.extend(modifiers);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    pub fn with_tag(mut self, tag: Span) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    pub fn span(&self) -> Option<&Substr> {
+        self.span.get()
+    }
+
+    /// Returns true if the value ends with the `raw` modifier and must not be escaped.
+    pub fn is_raw(&self) -> bool {
+        self.modifiers
+            .last()
+            .is_some_and(|(name, _)| name.as_str() == RAW_MODIFIER)
     }
 
     pub fn calc<VC: VariableContainer>(
         &self,
         context: &RenderContext<VC>,
-    ) -> crate::error::Result<Value> {
+    ) -> crate::error::Result<'_, Value> {
+        match self.evaluate(context)? {
+            ControlFlow::Continue(value) | ControlFlow::Break(value) => Ok(value),
+        }
+    }
+
+    /// Like [`CalculatedValue::calc`], but an expression made undefined by
+    /// [`RenderContext::undefined`] is [`Value::Null`]. Used by conditions and loops.
+    #[cfg(any(feature = "condition", feature = "foreach"))]
+    pub fn calc_or_null<VC: VariableContainer>(
+        &self,
+        context: &RenderContext<VC>,
+    ) -> crate::error::Result<'_, Value> {
+        match self.evaluate(context)? {
+            ControlFlow::Continue(value) => Ok(value),
+            ControlFlow::Break(_) => Ok(Value::Null),
+        }
+    }
+
+    /// Breaks with the value of the whole expression if it contains an unknown name that is
+    /// not replaced by a callback. Remaining modifiers and operators are skipped.
+    ///
+    /// An undefined value handled by a later modifier like `{a|upper|default:""}` skips the
+    /// registered modifiers in front of it.
+    fn evaluate<VC: VariableContainer>(
+        &self,
+        context: &RenderContext<VC>,
+    ) -> crate::error::Result<'_, ControlFlow<Value, Value>> {
+        let mut defined = true;
+        // Undefined and waiting for a modifier accepting undefined values
+        let mut pending = false;
         let mut var = match &self.value {
-            StorageMethod::Const(var) => Cow::Borrowed(var),
-            StorageMethod::Variable(var_name) => {
-                // Safety: var_name points to tpl.tpl_str and should never be null
-                let var_name = unsafe { var_name.as_ref().unwrap() };
-                let var = context.variables.get(var_name);
-                Cow::Borrowed(var.ok_or(crate::error::Error::UnknownVariable(var_name))?)
+            Operand::Value(value) => match value.get(&context.variables) {
+                Ok(value) => Cow::Borrowed(value),
+                Err(Error {
+                    kind: ErrorKind::UnknownVariable(_),
+                    ..
+                }) if self.accepts_undefined() => {
+                    defined = false;
+                    pending = true;
+                    Cow::Owned(Value::Null)
+                }
+                Err(e) => match self.replace_unknown(e, context)? {
+                    ControlFlow::Continue(value) => {
+                        defined = false;
+                        Cow::Owned(value)
+                    }
+                    ControlFlow::Break(value) => return Ok(ControlFlow::Break(value)),
+                },
+            },
+            Operand::Negate(value, operator) => {
+                let ControlFlow::Continue(value) = self.evaluate_operand(value, context)? else {
+                    return Ok(ControlFlow::Break(self.undefined_value(context)));
+                };
+                match value {
+                    Some(value) => {
+                        let result = modifier::sub(&Value::Integer(0), vec![&value]);
+                        Cow::Owned(result.map_err(|e| operator_error(e, operator))?)
+                    }
+                    None => {
+                        defined = false;
+                        pending = true;
+                        Cow::Owned(Value::Null)
+                    }
+                }
+            }
+            Operand::Operation(left, operator, span, right) => {
+                let ControlFlow::Continue(left) = self.evaluate_operand(left, context)? else {
+                    return Ok(ControlFlow::Break(self.undefined_value(context)));
+                };
+                let ControlFlow::Continue(right) = self.evaluate_operand(right, context)? else {
+                    return Ok(ControlFlow::Break(self.undefined_value(context)));
+                };
+                match (left, right) {
+                    (Some(left), Some(right)) => {
+                        let result = operator.apply(&left, &right);
+                        Cow::Owned(result.map_err(|e| operator_error(e, span))?)
+                    }
+                    _ => {
+                        defined = false;
+                        pending = true;
+                        Cow::Owned(Value::Null)
+                    }
+                }
             }
         };
 
-        for (modifier_name, args) in &self.modifiers {
-            // Safety: modifier_name points to tpl.tpl_str and should never be null
-            let modifier_name = unsafe { modifier_name.as_ref().unwrap() };
-            let modifier = context
-                .modifier
-                .get(modifier_name)
-                .ok_or(crate::error::Error::UnknownModifier(modifier_name))?;
-
-            let args = storage_methods_to_values(args, &context.variables)?;
-
-            var = match modifier(&var, args) {
-                Ok(v) => Cow::Owned(v),
-                Err(e) => {
-                    let error = e.to_string();
-                    error!("{}", error);
-                    return Err(crate::error::Error::Modifier(e));
+        for (modifier_span, args) in &self.modifiers {
+            let modifier_name = modifier_span.as_str();
+            var = match modifier_name {
+                RAW_MODIFIER => continue,
+                DEFAULT_MODIFIER => {
+                    let default = args.first().ok_or_else(|| {
+                        let e = modifier::Error::MissingArgument {
+                            argument_name: "default",
+                        };
+                        Error::new(ErrorKind::Modifier(e), Some(modifier_span))
+                    })?;
+                    match *var {
+                        Value::Null => match self.lookup(default, context)? {
+                            ControlFlow::Continue(value) => value,
+                            ControlFlow::Break(value) => return Ok(ControlFlow::Break(value)),
+                        },
+                        _ => var,
+                    }
+                }
+                IS_DEFINED_MODIFIER => Cow::Owned(Value::Bool(defined)),
+                IS_NULL_MODIFIER => Cow::Owned(Value::Bool(matches!(*var, Value::Null))),
+                _ => {
+                    let Some(modifier) = context.modifier.get(modifier_name) else {
+                        let e = ErrorKind::UnknownModifier(modifier_name);
+                        let e = Error::new(e, Some(modifier_span));
+                        match self.replace_unknown(e, context)? {
+                            ControlFlow::Continue(value) => var = Cow::Owned(value),
+                            ControlFlow::Break(value) => return Ok(ControlFlow::Break(value)),
+                        }
+                        continue;
+                    };
+                    if pending {
+                        continue;
+                    }
+
+                    let mut values = Vec::with_capacity(args.len());
+                    for arg in args {
+                        match self.lookup(arg, context)? {
+                            ControlFlow::Continue(value) => values.push(value),
+                            ControlFlow::Break(value) => return Ok(ControlFlow::Break(value)),
+                        }
+                    }
+                    let args = values.iter().map(|v| &**v).collect();
+
+                    match modifier(&var, args) {
+                        Ok(v) => Cow::Owned(v),
+                        Err(e) => {
+                            let error = e.to_string();
+                            error!("{}", error);
+                            return Err(Error::new(ErrorKind::Modifier(e), Some(modifier_span)));
+                        }
+                    }
                 }
             };
+            defined = true;
+            pending = false;
         }
 
-        Ok(var.into_owned())
+        Ok(ControlFlow::Continue(var.into_owned()))
+    }
+
+    /// Evaluates a part of an operation.
+    ///
+    /// Continues with `None` if the part is undefined and a modifier of this expression handles
+    /// undefined values like `(a + b)|default:0`. Breaks if this expression is undefined.
+    fn evaluate_operand<'t, VC: VariableContainer>(
+        &'t self,
+        operand: &'t CalculatedValue,
+        context: &RenderContext<VC>,
+    ) -> crate::error::Result<'t, ControlFlow<(), Option<Value>>> {
+        match operand.evaluate(context) {
+            Ok(ControlFlow::Continue(value)) => Ok(ControlFlow::Continue(Some(value))),
+            Err(Error {
+                kind: ErrorKind::UnknownVariable(_),
+                ..
+            })
+            | Ok(ControlFlow::Break(_))
+                if self.accepts_undefined() =>
+            {
+                Ok(ControlFlow::Continue(None))
+            }
+            Ok(ControlFlow::Break(_)) => Ok(ControlFlow::Break(())),
+            Err(e) => Err(e),
+        }
     }
-}
 
-fn storage_methods_to_values<'a, 't>(
-    args: &'a [StorageMethod],
-    variables: &'a dyn VariableContainer,
-) -> crate::error::Result<'t, Vec<&'a Value>> {
-    let mut real_args = Vec::with_capacity(args.len());
-
-    for arg in args {
-        let arg = match arg {
-            StorageMethod::Const(value) => value,
-            StorageMethod::Variable(var) =>
-            //Safety: var points to tpl.tpl_str and should never be null
-            unsafe {
-                let var = var.as_ref().unwrap();
-                variables
-                    .get(var)
-                    .ok_or(crate::error::Error::UnknownVariable(var))?
+    /// Returns the value of a modifier argument. Missing arguments are replaced by
+    /// [`Undefined::Null`] and [`Undefined::Empty`] instead of skipping the modifier.
+    fn lookup<'a, 't: 'a, VC: VariableContainer>(
+        &'t self,
+        value: &'t StorageMethod,
+        context: &'a RenderContext<VC>,
+    ) -> crate::error::Result<'t, ControlFlow<Value, Cow<'a, Value>>> {
+        match value.get(&context.variables) {
+            Ok(value) => Ok(ControlFlow::Continue(Cow::Borrowed(value))),
+            Err(e) => match self.replace_unknown(e, context)? {
+                ControlFlow::Break(value) if !matches!(context.undefined, Undefined::Keep) => {
+                    Ok(ControlFlow::Continue(Cow::Owned(value)))
+                }
+                ControlFlow::Break(value) => Ok(ControlFlow::Break(value)),
+                ControlFlow::Continue(value) => Ok(ControlFlow::Continue(Cow::Owned(value))),
             },
+        }
+    }
+
+    /// Applies [`RenderContext::undefined`] to an unknown variable or modifier.
+    ///
+    /// Returns the value of a callback replacing the unknown name or breaks with the value of
+    /// the whole expression.
+    fn replace_unknown<'t, VC: VariableContainer>(
+        &'t self,
+        error: Error<'t>,
+        context: &RenderContext<VC>,
+    ) -> crate::error::Result<'t, ControlFlow<Value, Value>> {
+        let unknown = match error.kind {
+            ErrorKind::UnknownVariable(name) => Unknown::Variable(name),
+            ErrorKind::UnknownModifier(name) => Unknown::Modifier(name),
+            _ => return Err(error),
         };
-        real_args.push(arg);
+        match &context.undefined {
+            Undefined::Error => return Err(error),
+            Undefined::Null => {}
+            Undefined::Empty => warn!("{}, using an empty string", error.kind),
+            Undefined::Keep => warn!("{}, keeping the tag", error.kind),
+            Undefined::Callback(callback) => {
+                return callback(unknown).map(ControlFlow::Continue).ok_or(error)
+            }
+        }
+        Ok(ControlFlow::Break(self.undefined_value(context)))
     }
-    Ok(real_args)
-}
 
-impl PartialEq for CalculatedValue {
-    fn eq(&self, other: &Self) -> bool {
-        if self.value != other.value {
-            return false;
+    /// Returns the value of this expression if it is undefined: [`Value::Null`], an empty
+    /// string or for [`Undefined::Keep`] the text of the tag or expression.
+    fn undefined_value<VC: VariableContainer>(&self, context: &RenderContext<VC>) -> Value {
+        match context.undefined {
+            Undefined::Empty => Value::String(String::new()),
+            Undefined::Keep => {
+                let text = self.tag.get().or_else(|| self.span());
+                Value::String(text.map(|s| s.as_str()).unwrap_or_default().to_owned())
+            }
+            _ => Value::Null,
         }
+    }
 
-        self.modifiers.iter().zip(&other.modifiers).all(|(s, o)|
-            // Safety: Both modifier names point to positions in the original template string.
-            unsafe {
-                s.0.as_ref() == o.0.as_ref() && s.1 == o.1
-            })
+    /// Returns true if a modifier handles undefined variables.
+    fn accepts_undefined(&self) -> bool {
+        self.modifiers
+            .iter()
+            .any(|(name, _)| UNDEFINED_MODIFIERS.contains(&name.as_str()))
     }
 }
+
+fn operator_error(e: modifier::Error, operator: &Span) -> Error<'_> {
+    error!("{}", e);
+    Error::new(ErrorKind::Modifier(e), operator.get())
+}
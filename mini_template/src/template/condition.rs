@@ -2,6 +2,7 @@ use crate::{renderer::RenderContext, variable_container::VariableContainer};
 
 use super::CalculatedValue;
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, PartialEq)]
 pub enum Condition {
     Or(OrCondition),
@@ -10,7 +11,7 @@ pub enum Condition {
     Compare(CompareCondition),
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "conditional"))]
 impl Condition {
     pub fn and(and: Vec<Condition>) -> Condition {
         Condition::And(AndCondition::new(and))
@@ -25,12 +26,12 @@ impl ConditionEval for Condition {
     fn eval<VC: VariableContainer>(
         &self,
         context: &RenderContext<VC>,
-    ) -> crate::error::Result<bool> {
+    ) -> crate::error::Result<'_, bool> {
         match self {
             Self::Or(c) => c.eval(context),
             Self::And(c) => c.eval(context),
             Self::Compare(c) => c.eval(context),
-            Self::CalculatedValue(c) => Ok(c.calc(context)?.as_bool()),
+            Self::CalculatedValue(c) => Ok(c.calc_or_null(context)?.as_bool()),
         }
     }
 }
@@ -39,7 +40,7 @@ pub trait ConditionEval {
     fn eval<VC: VariableContainer>(
         &self,
         context: &RenderContext<VC>,
-    ) -> crate::error::Result<bool>;
+    ) -> crate::error::Result<'_, bool>;
 }
 
 #[derive(Debug, PartialEq)]
@@ -57,7 +58,7 @@ impl ConditionEval for OrCondition {
     fn eval<VC: VariableContainer>(
         &self,
         context: &RenderContext<VC>,
-    ) -> crate::error::Result<bool> {
+    ) -> crate::error::Result<'_, bool> {
         for condition in &self.conditions {
             if condition.eval(context)? {
                 return Ok(true);
@@ -82,7 +83,7 @@ impl ConditionEval for AndCondition {
     fn eval<VC: VariableContainer>(
         &self,
         context: &RenderContext<VC>,
-    ) -> crate::error::Result<bool> {
+    ) -> crate::error::Result<'_, bool> {
         for condition in &self.conditions {
             if !condition.eval(context)? {
                 return Ok(false);
@@ -103,9 +104,9 @@ impl ConditionEval for CompareCondition {
     fn eval<VC: VariableContainer>(
         &self,
         context: &RenderContext<VC>,
-    ) -> crate::error::Result<bool> {
-        let left = self.left.calc(context)?;
-        let right = self.right.calc(context)?;
+    ) -> crate::error::Result<'_, bool> {
+        let left = self.left.calc_or_null(context)?;
+        let right = self.right.calc_or_null(context)?;
         let r = match self.operator {
             CompareOperator::EQ => left == right,
             CompareOperator::NE => left != right,
@@ -4,31 +4,49 @@ use crate::{renderer::RenderContext, variable_container::VariableContainer};
 
 use super::{
     condition::{Condition, ConditionEval},
-    Render, Statement,
+    Render, Span, Statement,
 };
 
 #[derive(Debug, PartialEq)]
 pub struct Conditional {
     pub(crate) condition: Condition,
     pub(crate) then_case: Vec<Statement>,
+    pub(crate) else_if_cases: Vec<(Condition, Vec<Statement>)>,
     pub(crate) else_case: Option<Vec<Statement>>,
+    pub(crate) span: Span,
+}
+
+impl Conditional {
+    fn eval<'a, VC: VariableContainer>(
+        &'a self,
+        condition: &'a Condition,
+        context: &RenderContext<VC>,
+    ) -> crate::error::Result<'a, bool> {
+        condition
+            .eval(context)
+            .map_err(|e| e.or_span(self.span.get()))
+    }
 }
 
 impl Render for Conditional {
-    fn render<VC: VariableContainer>(
-        &self,
-        context: &mut RenderContext<VC>,
-        buf: &mut String,
-    ) -> crate::error::Result<()> {
-        if self.condition.eval(context)? {
-            self.then_case.render(context, buf)
-        } else {
-            if let Some(e) = &self.else_case {
-                e.render(context, buf)
-            } else {
-                Ok(())
+    fn render<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        if self.eval(&self.condition, context)? {
+            return self.then_case.render(context, buf);
+        }
+        for (condition, case) in &self.else_if_cases {
+            if self.eval(condition, context)? {
+                return case.render(context, buf);
             }
         }
+        if let Some(e) = &self.else_case {
+            e.render(context, buf)
+        } else {
+            Ok(())
+        }
     }
 }
 
@@ -40,11 +58,13 @@ mod tests {
         renderer::RenderContext,
         template::{
             condition::{AndCondition, Condition, ConditionEval, OrCondition},
-            CalculatedValue, StorageMethod,
+            CalculatedValue, Render, Span, Statement, StorageMethod,
         },
         value::Value,
     };
 
+    use super::Conditional;
+
     #[test]
     fn eval_condition() {
         let condition = Condition::CalculatedValue(CalculatedValue::new(
@@ -59,8 +79,14 @@ mod tests {
     #[test]
     fn eval_condition_and() {
         let condition = AndCondition::new(vec![
-            Condition::CalculatedValue(CalculatedValue::new(StorageMethod::Variable("a"), vec![])),
-            Condition::CalculatedValue(CalculatedValue::new(StorageMethod::Variable("b"), vec![])),
+            Condition::CalculatedValue(CalculatedValue::new(
+                StorageMethod::Variable("a".into()),
+                vec![],
+            )),
+            Condition::CalculatedValue(CalculatedValue::new(
+                StorageMethod::Variable("b".into()),
+                vec![],
+            )),
         ]);
         let mut vars = HashMap::new();
         vars.insert("a".to_owned(), Value::Bool(true));
@@ -91,8 +117,14 @@ mod tests {
     #[test]
     fn eval_condition_or() {
         let condition = OrCondition::new(vec![
-            Condition::CalculatedValue(CalculatedValue::new(StorageMethod::Variable("a"), vec![])),
-            Condition::CalculatedValue(CalculatedValue::new(StorageMethod::Variable("b"), vec![])),
+            Condition::CalculatedValue(CalculatedValue::new(
+                StorageMethod::Variable("a".into()),
+                vec![],
+            )),
+            Condition::CalculatedValue(CalculatedValue::new(
+                StorageMethod::Variable("b".into()),
+                vec![],
+            )),
         ]);
         let mut vars = HashMap::new();
         vars.insert("a".to_owned(), Value::Bool(true));
@@ -125,7 +157,7 @@ mod tests {
         let mut vars = HashMap::new();
         vars.insert("my_var".to_owned(), Value::Bool(true));
         let condition = Condition::CalculatedValue(CalculatedValue::new(
-            StorageMethod::Variable("my_var"),
+            StorageMethod::Variable("my_var".into()),
             vec![],
         ));
         assert!(condition
@@ -138,7 +170,7 @@ mod tests {
         let mut vars = HashMap::new();
         vars.insert("my_var".to_owned(), Value::Bool(false));
         let condition = Condition::CalculatedValue(CalculatedValue::new(
-            StorageMethod::Variable("my_var"),
+            StorageMethod::Variable("my_var".into()),
             vec![],
         ));
         assert!(!condition
@@ -151,7 +183,7 @@ mod tests {
         let mut vars = HashMap::new();
         vars.insert("my_var".to_owned(), Value::Number(0.));
         let condition = Condition::CalculatedValue(CalculatedValue::new(
-            StorageMethod::Variable("my_var"),
+            StorageMethod::Variable("my_var".into()),
             vec![],
         ));
         assert!(!condition
@@ -164,7 +196,7 @@ mod tests {
         let mut vars = HashMap::new();
         vars.insert("my_var".to_owned(), Value::Number(1.));
         let condition = Condition::CalculatedValue(CalculatedValue::new(
-            StorageMethod::Variable("my_var"),
+            StorageMethod::Variable("my_var".into()),
             vec![],
         ));
         assert!(condition
@@ -177,7 +209,7 @@ mod tests {
         let mut vars = HashMap::new();
         vars.insert("my_var".to_owned(), Value::Number(10.));
         let condition = Condition::CalculatedValue(CalculatedValue::new(
-            StorageMethod::Variable("my_var"),
+            StorageMethod::Variable("my_var".into()),
             vec![],
         ));
         assert!(condition
@@ -191,16 +223,16 @@ mod tests {
         let condition = Condition::and(vec![
             Condition::or(vec![
                 Condition::CalculatedValue(CalculatedValue::new(
-                    StorageMethod::Variable("var1"),
+                    StorageMethod::Variable("var1".into()),
                     vec![],
                 )),
                 Condition::CalculatedValue(CalculatedValue::new(
-                    StorageMethod::Variable("var2"),
+                    StorageMethod::Variable("var2".into()),
                     vec![],
                 )),
             ]),
             Condition::CalculatedValue(CalculatedValue::new(
-                StorageMethod::Variable("var3"),
+                StorageMethod::Variable("var3".into()),
                 vec![],
             )),
         ]);
@@ -246,4 +278,89 @@ mod tests {
         vars.insert("var3".to_owned(), Value::Bool(true));
         assert!(condition.eval(&RenderContext::new(&mods, vars)).unwrap());
     }
+
+    #[test]
+    fn render_else_if() {
+        let conditional = Conditional {
+            condition: Condition::CalculatedValue(CalculatedValue::new(
+                StorageMethod::Variable("a".into()),
+                vec![],
+            )),
+            then_case: vec![Statement::Literal("A".into())],
+            else_if_cases: vec![
+                (
+                    Condition::CalculatedValue(CalculatedValue::new(
+                        StorageMethod::Variable("b".into()),
+                        vec![],
+                    )),
+                    vec![Statement::Literal("B".into())],
+                ),
+                (
+                    Condition::CalculatedValue(CalculatedValue::new(
+                        StorageMethod::Variable("c".into()),
+                        vec![],
+                    )),
+                    vec![Statement::Literal("C".into())],
+                ),
+            ],
+            else_case: Some(vec![Statement::Literal("ELSE".into())]),
+            span: Span::default(),
+        };
+        let modifiers = HashMap::new();
+        for (a, b, c, expected) in [
+            (true, true, true, "A"),
+            (false, true, true, "B"),
+            (false, false, true, "C"),
+            (false, false, false, "ELSE"),
+        ] {
+            let mut vars = HashMap::new();
+            vars.insert("a".to_owned(), Value::Bool(a));
+            vars.insert("b".to_owned(), Value::Bool(b));
+            vars.insert("c".to_owned(), Value::Bool(c));
+            let mut buf = String::new();
+            conditional
+                .render(&mut RenderContext::new(&modifiers, vars), &mut buf)
+                .unwrap();
+            assert_eq!(buf, expected);
+        }
+    }
+
+    #[test]
+    fn render_else_if_short_circuit() {
+        // `c` is unknown and must not be evaluated once `b` matched
+        let conditional = Conditional {
+            condition: Condition::CalculatedValue(CalculatedValue::new(
+                StorageMethod::Variable("a".into()),
+                vec![],
+            )),
+            then_case: vec![Statement::Literal("A".into())],
+            else_if_cases: vec![
+                (
+                    Condition::CalculatedValue(CalculatedValue::new(
+                        StorageMethod::Variable("b".into()),
+                        vec![],
+                    )),
+                    vec![Statement::Literal("B".into())],
+                ),
+                (
+                    Condition::CalculatedValue(CalculatedValue::new(
+                        StorageMethod::Variable("c".into()),
+                        vec![],
+                    )),
+                    vec![Statement::Literal("C".into())],
+                ),
+            ],
+            else_case: None,
+            span: Span::default(),
+        };
+        let modifiers = HashMap::new();
+        let mut vars = HashMap::new();
+        vars.insert("a".to_owned(), Value::Bool(false));
+        vars.insert("b".to_owned(), Value::Bool(true));
+        let mut buf = String::new();
+        conditional
+            .render(&mut RenderContext::new(&modifiers, vars), &mut buf)
+            .unwrap();
+        assert_eq!(buf, "B");
+    }
 }
@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    error::{Error, ErrorKind},
+    renderer::RenderContext,
+    value::Value,
+    variable_container::VariableContainer,
+};
+
+use super::{CalculatedValue, Render, Statement, Substr};
+
+/// Name of the variable holding the metadata of the current iteration.
+const LOOP_VARIABLE: &str = "loop";
+
+#[derive(Debug, PartialEq)]
+pub struct Foreach {
+    identifier: Substr,
+    collection: CalculatedValue,
+    template: Vec<Statement>,
+    else_case: Option<Vec<Statement>>,
+}
+
+impl Foreach {
+    pub fn new(
+        identifier: Substr,
+        collection: CalculatedValue,
+        template: Vec<Statement>,
+        else_case: Option<Vec<Statement>>,
+    ) -> Self {
+        Self {
+            identifier,
+            collection,
+            template,
+            else_case,
+        }
+    }
+}
+
+impl Render for Foreach {
+    fn render<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        let items = match self.collection.calc_or_null(context)? {
+            Value::Array(a) => a.into_iter().map(|v| (None, v)).collect::<Vec<_>>(),
+            Value::Map(m) => m.into_iter().map(|(k, v)| (Some(k), v)).collect(),
+            Value::Null => Vec::new(),
+            v => {
+                return Err(Error::new(
+                    ErrorKind::NotIterable(v),
+                    self.collection.span(),
+                ))
+            }
+        };
+
+        if items.is_empty() {
+            return match &self.else_case {
+                Some(e) => e.render(context, buf),
+                None => Ok(()),
+            };
+        }
+
+        context.variables.push_scope();
+        let result = self.render_items(items, context, buf);
+        context.variables.pop_scope();
+        result
+    }
+}
+
+impl Foreach {
+    /// Renders the body for every item. The item and `loop` are set in the current scope.
+    fn render_items<'a, VC: VariableContainer>(
+        &'a self,
+        items: Vec<(Option<String>, Value)>,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        let length = items.len();
+        for (index, (key, item)) in items.into_iter().enumerate() {
+            let mut meta = BTreeMap::from_iter([
+                (String::from("index"), Value::from(index)),
+                (String::from("first"), Value::Bool(index == 0)),
+                (String::from("last"), Value::Bool(index + 1 == length)),
+                (String::from("length"), Value::from(length)),
+            ]);
+            if let Some(key) = key {
+                meta.insert(String::from("key"), Value::String(key));
+            }
+            context
+                .variables
+                .set_local(self.identifier.to_string(), item);
+            context
+                .variables
+                .set_local(LOOP_VARIABLE.to_owned(), Value::Map(meta));
+            self.template.render(context, buf)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::{
+        error::ErrorKind,
+        renderer::RenderContext,
+        template::{CalculatedValue, PathSegment, Render, Statement, StorageMethod, VariablePath},
+        value::Value,
+    };
+
+    use super::Foreach;
+
+    #[test]
+    fn foreach_array() {
+        let f = Foreach::new(
+            "item".into(),
+            CalculatedValue::new(StorageMethod::Variable("items".into()), vec![]),
+            vec![
+                Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Variable("item".into()),
+                    vec![],
+                )),
+                Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Path(VariablePath::new(
+                        "loop.index".into(),
+                        "loop".into(),
+                        vec![(10, PathSegment::Key("index".into()))],
+                    )),
+                    vec![],
+                )),
+            ],
+            None,
+        );
+
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(
+            &modifiers,
+            HashMap::from_iter([(
+                "items".to_owned(),
+                Value::Array(vec![
+                    Value::String("a".to_owned()),
+                    Value::String("b".to_owned()),
+                    Value::String("c".to_owned()),
+                ]),
+            )]),
+        );
+        let mut buffer = String::new();
+        assert!(f.render(&mut ctx, &mut buffer).is_ok());
+        assert_eq!(buffer.as_str(), "a0b1c2")
+    }
+
+    #[test]
+    fn foreach_metadata() {
+        let f = Foreach::new(
+            "item".into(),
+            CalculatedValue::new(StorageMethod::Variable("items".into()), vec![]),
+            vec![Statement::Calculated(CalculatedValue::new(
+                StorageMethod::Variable("loop".into()),
+                vec![],
+            ))],
+            None,
+        );
+
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(
+            &modifiers,
+            HashMap::from_iter([(
+                "items".to_owned(),
+                Value::Map(BTreeMap::from_iter([
+                    ("x".to_owned(), Value::Number(1.)),
+                    ("y".to_owned(), Value::Number(2.)),
+                ])),
+            )]),
+        );
+        let mut buffer = String::new();
+        assert!(f.render(&mut ctx, &mut buffer).is_ok());
+        assert_eq!(
+            buffer.as_str(),
+            "{first: true, index: 0, key: x, last: false, length: 2}\
+            {first: false, index: 1, key: y, last: true, length: 2}"
+        )
+    }
+
+    #[test]
+    fn foreach_empty_else() {
+        let f = Foreach::new(
+            "item".into(),
+            CalculatedValue::new(StorageMethod::Variable("items".into()), vec![]),
+            vec![Statement::Literal("ITEM".into())],
+            Some(vec![Statement::Literal("EMPTY".into())]),
+        );
+
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(
+            &modifiers,
+            HashMap::from_iter([("items".to_owned(), Value::Array(vec![]))]),
+        );
+        let mut buffer = String::new();
+        assert!(f.render(&mut ctx, &mut buffer).is_ok());
+        assert_eq!(buffer.as_str(), "EMPTY")
+    }
+
+    #[test]
+    fn foreach_not_iterable() {
+        let f = Foreach::new(
+            "item".into(),
+            CalculatedValue::new(StorageMethod::Const(Value::Number(1.)), vec![]),
+            vec![Statement::Literal("ITEM".into())],
+            None,
+        );
+
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(&modifiers, HashMap::new());
+        let mut buffer = String::new();
+        assert_eq!(
+            f.render(&mut ctx, &mut buffer),
+            Err(ErrorKind::NotIterable(Value::Number(1.)).into())
+        );
+    }
+}
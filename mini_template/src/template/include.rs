@@ -0,0 +1,192 @@
+use crate::{
+    error::{Error, ErrorKind},
+    renderer::RenderContext,
+    variable_container::VariableContainer,
+};
+
+use super::{Assign, Render, Span, StorageMethod, Template};
+
+#[derive(Debug, PartialEq)]
+pub struct Include {
+    template: StorageMethod,
+    assigns: Vec<Assign>,
+    span: Span,
+}
+
+impl Include {
+    pub fn new(template: StorageMethod, assigns: Vec<Assign>) -> Self {
+        Self {
+            template,
+            assigns,
+            span: Span::default(),
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+}
+
+impl Render for Include {
+    fn render<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        let key = self.template.get(&context.variables)?;
+        let template = context
+            .templates
+            .and_then(|templates| templates.get(key))
+            .ok_or_else(|| Error::new(ErrorKind::UnknownTemplate, self.span.get()))?;
+        if context
+            .template_stack
+            .iter()
+            .any(|t| std::ptr::eq(*t, template))
+        {
+            return Err(Error::new(
+                ErrorKind::CyclicTemplate(key.to_string()),
+                self.span.get(),
+            ));
+        }
+
+        // Variables passed with `with` and assigned by the included template stay inside it
+        context.variables.push_scope();
+        let result = self.render_included(template, context, buf);
+        context.variables.pop_scope();
+        result
+    }
+}
+
+impl Include {
+    fn render_included<'a, VC: VariableContainer>(
+        &'a self,
+        template: &'a Template,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        for assign in &self.assigns {
+            assign.assign_local(context)?;
+        }
+
+        // Blocks of the including template must not replace blocks of the included template.
+        #[cfg(feature = "inheritance")]
+        let block_overrides = std::mem::take(&mut context.block_overrides);
+        let result = template.render(context, buf);
+        #[cfg(feature = "inheritance")]
+        {
+            context.block_overrides = block_overrides;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        error::{Error, ErrorKind},
+        parser::{parse, ParseOptions},
+        renderer::RenderContext,
+        template::{Render, Template},
+        value::Value,
+    };
+
+    fn templates(templates: &[(&str, &str)]) -> HashMap<String, Template> {
+        templates
+            .iter()
+            .map(|(k, v)| (k.to_string(), parse(v.to_string(), ParseOptions::default()).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn include() {
+        let templates = templates(&[
+            ("main", r#"Hello {include "name"}!"#),
+            ("name", "{name}"),
+        ]);
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(
+            &modifiers,
+            HashMap::from_iter([("name".to_owned(), Value::String("World".to_owned()))]),
+        );
+        ctx.templates = Some(&templates);
+        let mut buffer = String::new();
+        assert!(templates["main"].render(&mut ctx, &mut buffer).is_ok());
+        assert_eq!(buffer.as_str(), "Hello World!")
+    }
+
+    #[test]
+    fn include_with() {
+        let templates = templates(&[
+            (
+                "main",
+                r#"{item=0}{include "row" with item=a} {include "row" with item="b" n=2} {item}"#,
+            ),
+            ("row", "{item}{n}"),
+        ]);
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(
+            &modifiers,
+            HashMap::from_iter([
+                ("a".to_owned(), Value::String("a".to_owned())),
+                ("n".to_owned(), Value::Number(1.)),
+            ]),
+        );
+        ctx.templates = Some(&templates);
+        let mut buffer = String::new();
+        assert!(templates["main"].render(&mut ctx, &mut buffer).is_ok());
+        assert_eq!(buffer.as_str(), "a1 b2 0")
+    }
+
+    #[test]
+    fn include_unknown_template() {
+        let templates = templates(&[("main", r#"{include "missing"}"#)]);
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(&modifiers, HashMap::new());
+        ctx.templates = Some(&templates);
+        let mut buffer = String::new();
+        assert_eq!(
+            templates["main"].render(&mut ctx, &mut buffer),
+            Err(Error::new(
+                ErrorKind::UnknownTemplate,
+                Some(&r#"{include "missing"}"#.into())
+            ))
+        );
+    }
+
+    #[test]
+    fn include_cycle() {
+        let templates = templates(&[
+            ("a", r#"{include "b"}"#),
+            ("b", r#"{include "c"}"#),
+            ("c", r#"{include "a"}"#),
+        ]);
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(&modifiers, HashMap::new());
+        ctx.templates = Some(&templates);
+        let mut buffer = String::new();
+        assert_eq!(
+            templates["a"].render(&mut ctx, &mut buffer),
+            Err(Error::new(
+                ErrorKind::CyclicTemplate("a".to_owned()),
+                Some(&r#"{include "a"}"#.into())
+            ))
+        );
+    }
+
+    #[test]
+    fn include_same_template_twice() {
+        let templates = templates(&[
+            ("main", r#"{include "part"}{include "part"}"#),
+            ("part", "x"),
+        ]);
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(&modifiers, HashMap::<String, Value>::new());
+        ctx.templates = Some(&templates);
+        let mut buffer = String::new();
+        assert!(templates["main"].render(&mut ctx, &mut buffer).is_ok());
+        assert_eq!(buffer.as_str(), "xx")
+    }
+}
@@ -0,0 +1,240 @@
+use crate::{
+    error::{Error, ErrorKind},
+    renderer::RenderContext,
+    variable_container::VariableContainer,
+};
+
+use super::{Render, Span, Statement, StorageMethod, Substr, Template};
+
+/// `{extends "base"}` renders the given template instead of the current one.
+/// Blocks of the current template replace the blocks with the same name in the base template.
+#[derive(Debug, PartialEq)]
+pub struct Extends {
+    template: StorageMethod,
+    span: Span,
+}
+
+impl Extends {
+    pub fn new(template: StorageMethod) -> Self {
+        Self {
+            template,
+            span: Span::default(),
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    pub fn render_parent<'a, VC: VariableContainer>(
+        &'a self,
+        child: &'a Template,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        let key = self.template.get(&context.variables)?;
+        let parent = context
+            .templates
+            .and_then(|templates| templates.get(key))
+            .ok_or_else(|| Error::new(ErrorKind::UnknownTemplate, self.span.get()))?;
+        if context
+            .template_stack
+            .iter()
+            .any(|t| std::ptr::eq(*t, parent))
+        {
+            return Err(Error::new(
+                ErrorKind::CyclicTemplate(key.to_string()),
+                self.span.get(),
+            ));
+        }
+
+        context.block_overrides.push(child);
+        let result = parent.render(context, buf);
+        context.block_overrides.pop();
+        result
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    name: Substr,
+    template: Vec<Statement>,
+}
+
+impl Block {
+    pub fn new(name: Substr, template: Vec<Statement>) -> Self {
+        Self { name, template }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Render for Block {
+    fn render<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        // The most derived template comes first. The block itself is the last fallback.
+        let mut chain = context
+            .block_overrides
+            .iter()
+            .filter_map(|t| Some((Some(*t), find_block(&t.tpl, self.name())?)))
+            .collect::<Vec<_>>();
+        if !chain.iter().any(|(_, b)| std::ptr::eq(*b, self)) {
+            chain.push((None, self));
+        }
+        render_chain(&chain, context, buf)
+    }
+}
+
+/// `{parent}` renders the content the current block would have without being overridden.
+#[derive(Debug, PartialEq)]
+pub struct Parent;
+
+impl Render for Parent {
+    fn render<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        match context.block_parents.last() {
+            Some(parents) if !parents.is_empty() => {
+                let parents = parents.clone();
+                render_chain(&parents, context, buf)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Renders the first block of `chain`. Errors are attributed to the template the block is
+/// defined in, blocks without a template belong to the rendered template.
+fn render_chain<'a, VC: VariableContainer>(
+    chain: &[(Option<&'a Template>, &'a Block)],
+    context: &mut RenderContext<'a, VC>,
+    buf: &mut dyn std::fmt::Write,
+) -> crate::error::Result<'a, ()> {
+    let ((owner, block), parents) = chain.split_first().unwrap();
+    context.block_parents.push(parents.to_vec());
+    context.variables.push_scope();
+    let result = block.template.render(context, buf);
+    context.variables.pop_scope();
+    context.block_parents.pop();
+    match owner {
+        Some(owner) => result.map_err(|e| e.or_template(owner)),
+        None => result,
+    }
+}
+
+fn find_block<'a>(statements: &'a [Statement], name: &str) -> Option<&'a Block> {
+    statements.iter().find_map(|statement| match statement {
+        Statement::Block(b) if b.name() == name => Some(b),
+        Statement::Block(b) => find_block(&b.template, name),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        error::ErrorKind,
+        parser::{parse, ParseOptions},
+        renderer::RenderContext,
+        template::{Render, Template},
+        value::Value,
+    };
+
+    fn render(templates: &[(&str, &str)], key: &str) -> Result<String, String> {
+        let templates: HashMap<String, Template> = templates
+            .iter()
+            .map(|(k, v)| (k.to_string(), parse(v.to_string(), ParseOptions::default()).unwrap()))
+            .collect();
+        let modifiers = HashMap::new();
+        let mut ctx = RenderContext::new(
+            &modifiers,
+            HashMap::from_iter([("name".to_owned(), Value::String("World".to_owned()))]),
+        );
+        ctx.templates = Some(&templates);
+        let mut buffer = String::new();
+        templates[key]
+            .render(&mut ctx, &mut buffer)
+            .map_err(|e| e.to_string())?;
+        Ok(buffer)
+    }
+
+    const BASE: &str = "<h1>{block title}Default{endblock}</h1><p>{block content}{endblock}</p>";
+
+    #[test]
+    fn block_without_extends() {
+        assert_eq!(
+            render(&[("base", BASE)], "base"),
+            Ok(String::from("<h1>Default</h1><p></p>"))
+        );
+    }
+
+    #[test]
+    fn override_block() {
+        let child = r#"{extends "base"}{block content}Hello {name}{endblock}ignored"#;
+        assert_eq!(
+            render(&[("base", BASE), ("child", child)], "child"),
+            Ok(String::from("<h1>Default</h1><p>Hello World</p>"))
+        );
+    }
+
+    #[test]
+    fn parent_block() {
+        let child = r#"{extends "base"}{block title}{parent} Title{endblock}"#;
+        assert_eq!(
+            render(&[("base", BASE), ("child", child)], "child"),
+            Ok(String::from("<h1>Default Title</h1><p></p>"))
+        );
+    }
+
+    #[test]
+    fn multi_level() {
+        let middle = r#"{extends "base"}{block title}Middle{endblock}"#;
+        let child = r#"{extends "middle"}{block title}{parent}/{parent}{endblock}"#;
+        assert_eq!(
+            render(
+                &[("base", BASE), ("middle", middle), ("child", child)],
+                "child"
+            ),
+            Ok(String::from("<h1>Middle/Middle</h1><p></p>"))
+        );
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let base = "{block outer}[{block inner}base{endblock}]{endblock}";
+        let child = r#"{extends "base"}{block inner}child{endblock}"#;
+        assert_eq!(
+            render(&[("base", base), ("child", child)], "child"),
+            Ok(String::from("[child]"))
+        );
+    }
+
+    #[test]
+    fn extends_cycle() {
+        let a = r#"{extends "b"}"#;
+        let b = r#"{extends "a"}"#;
+        assert_eq!(
+            render(&[("a", a), ("b", b)], "a"),
+            Err(ErrorKind::CyclicTemplate(String::from("a")).to_string())
+        );
+    }
+
+    #[test]
+    fn extends_unknown_template() {
+        let child = r#"{extends "missing"}"#;
+        assert_eq!(
+            render(&[("child", child)], "child"),
+            Err(ErrorKind::UnknownTemplate.to_string())
+        );
+    }
+}
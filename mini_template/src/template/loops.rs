@@ -2,12 +2,13 @@ use crate::{renderer::RenderContext, variable_container::VariableContainer};
 
 #[cfg(feature = "condition")]
 use super::condition::{Condition, ConditionEval};
-use super::{Render, Statement};
+use super::{Render, Span, Statement};
 
 #[derive(PartialEq, Debug)]
 pub struct Loop {
     condition: Condition,
     template: Vec<Statement>,
+    span: Span,
 }
 
 impl Loop {
@@ -15,17 +16,41 @@ impl Loop {
         Self {
             condition,
             template,
+            span: Span::default(),
         }
     }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
 }
 
 impl Render for Loop {
-    fn render<VC: VariableContainer>(
-        &self,
-        context: &mut RenderContext<VC>,
-        buf: &mut String,
-    ) -> crate::error::Result<()> {
-        while self.condition.eval(context)? {
+    fn render<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        // Variables first assigned inside the loop are scoped to it
+        context.variables.push_scope();
+        let result = self.render_body(context, buf);
+        context.variables.pop_scope();
+        result
+    }
+}
+
+impl Loop {
+    fn render_body<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn std::fmt::Write,
+    ) -> crate::error::Result<'a, ()> {
+        while self
+            .condition
+            .eval(context)
+            .map_err(|e| e.or_span(self.span.get()))?
+        {
             self.template.render(context, buf)?
         }
         Ok(())
@@ -50,16 +75,19 @@ mod tests {
     fn loop_single_iteration() {
         let l = Loop::new(
             Condition::CalculatedValue(CalculatedValue::new(
-                StorageMethod::Variable("var"),
+                StorageMethod::Variable("var".into()),
                 vec![],
             )),
             vec![
-                Statement::Calculated(CalculatedValue::new(StorageMethod::Variable("var"), vec![])),
+                Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Variable("var".into()),
+                    vec![],
+                )),
                 Statement::Assign(Assign::new(
-                    "var",
+                    "var".into(),
                     CalculatedValue::new(
-                        StorageMethod::Variable("var"),
-                        vec![("sub", vec![StorageMethod::Const(Value::Number(1.))])],
+                        StorageMethod::Variable("var".into()),
+                        vec![("sub".into(), vec![StorageMethod::Const(Value::Number(1.))])],
                     ),
                 )),
             ],
@@ -81,16 +109,19 @@ mod tests {
     fn loop_multiple_iterations() {
         let l = Loop::new(
             Condition::CalculatedValue(CalculatedValue::new(
-                StorageMethod::Variable("var"),
+                StorageMethod::Variable("var".into()),
                 vec![],
             )),
             vec![
-                Statement::Calculated(CalculatedValue::new(StorageMethod::Variable("var"), vec![])),
+                Statement::Calculated(CalculatedValue::new(
+                    StorageMethod::Variable("var".into()),
+                    vec![],
+                )),
                 Statement::Assign(Assign::new(
-                    "var",
+                    "var".into(),
                     CalculatedValue::new(
-                        StorageMethod::Variable("var"),
-                        vec![("sub", vec![StorageMethod::Const(Value::Number(1.))])],
+                        StorageMethod::Variable("var".into()),
+                        vec![("sub".into(), vec![StorageMethod::Const(Value::Number(1.))])],
                     ),
                 )),
             ],
@@ -115,7 +146,7 @@ mod tests {
                 StorageMethod::Const(Value::Bool(false)),
                 vec![],
             )),
-            vec![Statement::Literal("TEST")],
+            vec![Statement::Literal("TEST".into())],
         );
 
         let modifiers = HashMap::new();
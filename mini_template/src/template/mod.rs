@@ -5,61 +5,133 @@ mod calculated_value;
 pub mod condition;
 #[cfg(feature = "conditional")]
 mod conditional;
+#[cfg(feature = "foreach")]
+mod foreach;
+#[cfg(feature = "include")]
+mod include;
+#[cfg(feature = "inheritance")]
+mod inheritance;
 #[cfg(feature = "loop")]
 mod loops;
 mod statement;
 mod storage_method;
+mod substr;
 
 #[cfg(feature = "assign")]
 pub use assign::Assign;
-pub use calculated_value::CalculatedValue;
+pub(crate) use calculated_value::BUILTIN_MODIFIERS;
+pub use calculated_value::{CalculatedValue, Operator};
 #[cfg(feature = "conditional")]
 pub use conditional::*;
+#[cfg(feature = "foreach")]
+pub use foreach::Foreach;
+#[cfg(feature = "include")]
+pub use include::Include;
+#[cfg(feature = "inheritance")]
+pub use inheritance::{Block, Extends, Parent};
 #[cfg(feature = "loop")]
 pub use loops::Loop;
 pub use statement::Statement;
-pub use storage_method::StorageMethod;
+pub use storage_method::{PathSegment, StorageMethod, VariablePath};
+pub use substr::Substr;
 
-use crate::{error::Result, renderer::RenderContext, variable_container::VariableContainer};
+use std::{fmt::Write, sync::Arc};
+
+use crate::{
+    error::{Error, Result},
+    renderer::RenderContext,
+    variable_container::VariableContainer,
+};
+
+/// Part of the template source a statement was parsed from.
+///
+/// Spans are only used to report error positions. They are ignored when statements are compared.
+#[derive(Debug, Clone, Default)]
+pub struct Span(Option<Substr>);
+
+impl Span {
+    pub fn new(source: Substr) -> Self {
+        Self(Some(source))
+    }
+
+    pub fn get(&self) -> Option<&Substr> {
+        self.0.as_ref()
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub struct Template {
-    pub(crate) tpl_str: String,
+    /// Set by [`crate::MiniTemplate::add_named_template`]
+    pub(crate) name: Option<String>,
+    pub(crate) tpl_str: Arc<str>,
     pub(crate) tpl: Vec<Statement>,
 }
 
+impl Template {
+    #[cfg(feature = "inheritance")]
+    fn extends(&self) -> Option<&Extends> {
+        self.tpl.iter().find_map(|statement| match statement {
+            Statement::Extends(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    #[cfg(not(feature = "inheritance"))]
+    fn extends(&self) -> Option<()> {
+        None
+    }
+}
+
 impl Render for Template {
-    fn render<VC: VariableContainer>(
-        &self,
-        context: &mut RenderContext<VC>,
-        buf: &mut String,
-    ) -> Result<()> {
-        self.tpl.render(context, buf)
+    fn render<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn Write,
+    ) -> Result<'a, ()> {
+        context.template_stack.push(self);
+        let result = match self.extends() {
+            #[cfg(feature = "inheritance")]
+            Some(extends) => extends.render_parent(self, context, buf),
+            _ => self.tpl.render(context, buf),
+        };
+        context.template_stack.pop();
+        result.map_err(|e| e.or_template(self))
     }
 }
 
 pub trait Render {
-    fn render<VC: VariableContainer>(
-        &self,
-        context: &mut RenderContext<VC>,
-        buf: &mut String,
-    ) -> Result<()>;
+    fn render<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn Write,
+    ) -> Result<'a, ()>;
 }
 
 impl Render for Vec<Statement> {
-    fn render<VC: VariableContainer>(
-        &self,
-        context: &mut RenderContext<VC>,
-        buf: &mut String,
-    ) -> Result<()> {
+    fn render<'a, VC: VariableContainer>(
+        &'a self,
+        context: &mut RenderContext<'a, VC>,
+        buf: &mut dyn Write,
+    ) -> Result<'a, ()> {
         for statement in self {
             match statement {
-                Statement::Literal(literal) =>
-                // Safety: literal points to tpl.tpl_str and should never be null
-                unsafe { buf.push_str(literal.as_ref().unwrap()) },
+                Statement::Literal(literal) => buf
+                    .write_str(literal)
+                    .map_err(|e| Error::from(e).or_span(Some(literal)))?,
                 Statement::Calculated(cv) => {
-                    let var = cv.calc(context)?;
-                    buf.push_str(&var.to_string()[..])
+                    let var = cv.calc(context)?.to_string();
+                    if cv.is_raw() {
+                        buf.write_str(&var)
+                    } else {
+                        context.escape.escape_into(&var, buf)
+                    }
+                    .map_err(|e| Error::from(e).or_span(cv.span()))?
                 }
                 #[cfg(feature = "conditional")]
                 Statement::Condition(c) => c.render(context, buf)?,
@@ -67,6 +139,17 @@ impl Render for Vec<Statement> {
                 Statement::Assign(a) => a.assign(context)?,
                 #[cfg(feature = "loop")]
                 Statement::Loop(l) => l.render(context, buf)?,
+                #[cfg(feature = "foreach")]
+                Statement::Foreach(f) => f.render(context, buf)?,
+                #[cfg(feature = "include")]
+                Statement::Include(i) => i.render(context, buf)?,
+                // Handled by Template::render
+                #[cfg(feature = "inheritance")]
+                Statement::Extends(_) => {}
+                #[cfg(feature = "inheritance")]
+                Statement::Block(b) => b.render(context, buf)?,
+                #[cfg(feature = "inheritance")]
+                Statement::Parent(p) => p.render(context, buf)?,
             }
         }
 
@@ -1,36 +1,60 @@
 #[cfg(feature = "assign")]
 use super::assign::Assign;
-use super::CalculatedValue;
+use super::{CalculatedValue, Substr};
 #[cfg(feature = "conditional")]
 use super::Conditional;
+#[cfg(feature = "foreach")]
+use super::Foreach;
+#[cfg(feature = "include")]
+use super::Include;
+#[cfg(feature = "inheritance")]
+use super::{Block, Extends, Parent};
 #[cfg(feature = "loop")]
 use super::Loop;
 
 #[derive(Debug)]
 pub enum Statement {
-    Literal(*const str),
+    Literal(Substr),
     Calculated(CalculatedValue),
     #[cfg(feature = "conditional")]
-    Condition(Conditional),
+    Condition(Box<Conditional>),
     #[cfg(feature = "assign")]
     Assign(Assign),
     #[cfg(feature = "loop")]
-    Loop(Loop),
+    Loop(Box<Loop>),
+    #[cfg(feature = "foreach")]
+    Foreach(Foreach),
+    #[cfg(feature = "include")]
+    Include(Include),
+    #[cfg(feature = "inheritance")]
+    Extends(Extends),
+    #[cfg(feature = "inheritance")]
+    Block(Block),
+    #[cfg(feature = "inheritance")]
+    Parent(Parent),
 }
 
 impl PartialEq for Statement {
     fn eq(&self, other: &Statement) -> bool {
         match (self, other) {
             (Statement::Calculated(s), Statement::Calculated(o)) => s == o,
-            (Statement::Literal(s), &Statement::Literal(o)) =>
-            // Safety: Both literals point to positions in the original template string.
-            unsafe { s.as_ref() == o.as_ref() },
+            (Statement::Literal(s), Statement::Literal(o)) => s == o,
             #[cfg(feature = "conditional")]
             (Statement::Condition(s), Statement::Condition(o)) => s == o,
             #[cfg(feature = "assign")]
             (Statement::Assign(s), Statement::Assign(o)) => s == o,
             #[cfg(feature = "loop")]
             (Statement::Loop(s), Statement::Loop(o)) => s == o,
+            #[cfg(feature = "foreach")]
+            (Statement::Foreach(s), Statement::Foreach(o)) => s == o,
+            #[cfg(feature = "include")]
+            (Statement::Include(s), Statement::Include(o)) => s == o,
+            #[cfg(feature = "inheritance")]
+            (Statement::Extends(s), Statement::Extends(o)) => s == o,
+            #[cfg(feature = "inheritance")]
+            (Statement::Block(s), Statement::Block(o)) => s == o,
+            #[cfg(feature = "inheritance")]
+            (Statement::Parent(s), Statement::Parent(o)) => s == o,
             _ => false,
         }
     }
@@ -38,7 +62,7 @@ impl PartialEq for Statement {
 
 #[cfg(test)]
 mod tests {
-    use crate::template::{CalculatedValue, StorageMethod};
+    use crate::template::{CalculatedValue, StorageMethod, Substr};
 
     use super::Statement;
 
@@ -46,8 +70,8 @@ mod tests {
     fn two_literals_eq() {
         let str1 = "my literal value in a text";
         let str2 = "same literal value in an other text";
-        let literal1 = Statement::Literal(&str1[3..16]);
-        let literal2 = Statement::Literal(&str2[5..18]);
+        let literal1 = Statement::Literal(Substr::from(&str1[3..16]));
+        let literal2 = Statement::Literal(Substr::from(&str2[5..18]));
         assert_eq!(literal1, literal2);
     }
 
@@ -55,8 +79,8 @@ mod tests {
     fn two_literals_not_eq() {
         let str1 = "my literal value in a text";
         let str2 = "other LITERAL value in an other text";
-        let literal1 = Statement::Literal(&str1[3..16]);
-        let literal2 = Statement::Literal(&str2[5..18]);
+        let literal1 = Statement::Literal(Substr::from(&str1[3..16]));
+        let literal2 = Statement::Literal(Substr::from(&str2[5..18]));
         assert_ne!(literal1, literal2);
     }
 
@@ -65,11 +89,11 @@ mod tests {
         let str1 = "my var in a text";
         let str2 = "same var in an other text";
         let calculated1 = Statement::Calculated(CalculatedValue::new(
-            StorageMethod::Variable(&str1[3..6]),
+            StorageMethod::Variable(Substr::from(&str1[3..6])),
             vec![],
         ));
         let calculated2 = Statement::Calculated(CalculatedValue::new(
-            StorageMethod::Variable(&str2[5..8]),
+            StorageMethod::Variable(Substr::from(&str2[5..8])),
             vec![],
         ));
         assert_eq!(calculated1, calculated2);
@@ -80,11 +104,11 @@ mod tests {
         let str1 = "my var in a text";
         let str2 = "other VAR in an other text";
         let calculated1 = Statement::Calculated(CalculatedValue::new(
-            StorageMethod::Variable(&str1[3..6]),
+            StorageMethod::Variable(Substr::from(&str1[3..6])),
             vec![],
         ));
         let calculated2 = Statement::Calculated(CalculatedValue::new(
-            StorageMethod::Variable(&str2[5..8]),
+            StorageMethod::Variable(Substr::from(&str2[5..8])),
             vec![],
         ));
         assert_ne!(calculated1, calculated2);
@@ -1,31 +1,77 @@
-use std::fmt::Debug;
+use crate::{
+    error::{Error, ErrorKind},
+    value::Value,
+    variable_container::VariableContainer,
+};
 
-use crate::value::Value;
+use super::Substr;
 
+#[derive(Debug, PartialEq)]
 pub enum StorageMethod {
     Const(Value),
-    Variable(*const str),
+    Variable(Substr),
+    Path(VariablePath),
 }
 
-impl PartialEq for StorageMethod {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (StorageMethod::Const(s), StorageMethod::Const(o)) => s == o,
-            (StorageMethod::Variable(s), StorageMethod::Variable(o)) =>
-            // Safety: Both variable names point to positions in the original template string.
-            unsafe { s.as_ref() == o.as_ref() },
-            _ => false,
+impl StorageMethod {
+    pub fn get<'a, 't: 'a>(
+        &'t self,
+        variables: &'a dyn VariableContainer,
+    ) -> crate::error::Result<'t, &'a Value> {
+        match self {
+            Self::Const(value) => Ok(value),
+            Self::Variable(var_name) => variables
+                .get(var_name)
+                .ok_or_else(|| Error::new(ErrorKind::UnknownVariable(var_name), Some(var_name))),
+            Self::Path(path) => path.get(variables),
         }
     }
 }
 
-impl Debug for StorageMethod {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Const(c) => write!(f, "Const({c:#?})"),
-            Self::Variable(v) => unsafe {
-                write!(f, "Variable({:#?} \"{}\")", v, v.as_ref().unwrap())
-            },
+/// A variable reference that walks into nested values, like `customer.address.city` or `items[0].name`.
+#[derive(Debug, PartialEq)]
+pub struct VariablePath {
+    path: Substr,
+    root: Substr,
+    /// Every segment is stored together with the offset in `path` where it ends.
+    segments: Vec<(usize, PathSegment)>,
+}
+
+impl VariablePath {
+    pub fn new(path: Substr, root: Substr, segments: Vec<(usize, PathSegment)>) -> Self {
+        Self {
+            path,
+            root,
+            segments,
         }
     }
+
+    pub fn get<'a, 't: 'a>(
+        &'t self,
+        variables: &'a dyn VariableContainer,
+    ) -> crate::error::Result<'t, &'a Value> {
+        let mut value = variables
+            .get(&self.root)
+            .ok_or_else(|| Error::new(ErrorKind::UnknownVariable(&self.root), Some(&self.root)))?;
+
+        for (end, segment) in &self.segments {
+            let next = match (segment, value) {
+                (PathSegment::Key(key), Value::Map(map)) => map.get(key.as_str()),
+                (PathSegment::Index(index), Value::Array(array)) => array.get(*index),
+                _ => None,
+            };
+            value = next.ok_or_else(|| {
+                let span = self.path.substr(0..*end);
+                Error::new(ErrorKind::UnknownVariable(&self.path[..*end]), Some(&span))
+            })?;
+        }
+
+        Ok(value)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PathSegment {
+    Key(Substr),
+    Index(usize),
 }
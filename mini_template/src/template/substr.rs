@@ -0,0 +1,113 @@
+use std::{
+    fmt::{Debug, Display},
+    ops::{Deref, Range},
+    sync::Arc,
+};
+
+/// A part of the template source.
+///
+/// All parts of a template share its source, so parsing does not copy any text and the compiled
+/// template can be shared between threads.
+#[derive(Clone)]
+pub struct Substr {
+    source: Arc<str>,
+    start: usize,
+    end: usize,
+}
+
+impl Substr {
+    /// # Panics
+    /// Panics if `range` is out of bounds or does not lie on char boundaries.
+    pub fn new(source: &Arc<str>, range: Range<usize>) -> Self {
+        assert!(
+            source.get(range.clone()).is_some(),
+            "{range:?} is not a valid range in the template source"
+        );
+        Self {
+            source: Arc::clone(source),
+            start: range.start,
+            end: range.end,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source[self.start..self.end]
+    }
+
+    /// Returns the part of this substring in `range`, relative to its start.
+    pub fn substr(&self, range: Range<usize>) -> Self {
+        Self::new(&self.source, self.start + range.start..self.start + range.end)
+    }
+
+    /// The whole template source this is a part of
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Offset of the first byte in [`Substr::source`]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+}
+
+impl Deref for Substr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Substr {
+    fn from(s: &str) -> Self {
+        let source = Arc::from(s);
+        Self::new(&source, 0..s.len())
+    }
+}
+
+impl PartialEq for Substr {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Debug for Substr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for Substr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::Substr;
+
+    #[test]
+    fn shares_source() {
+        let source: Arc<str> = Arc::from("Hello {name}!");
+        let name = Substr::new(&source, 7..11);
+        assert_eq!(name.as_str(), "name");
+        assert!(std::ptr::eq(name.as_ptr(), source[7..].as_ptr()));
+    }
+
+    #[test]
+    fn eq_compares_content() {
+        let source: Arc<str> = Arc::from("a name and another name");
+        assert_eq!(Substr::new(&source, 2..6), Substr::new(&source, 19..23));
+        assert_eq!(Substr::new(&source, 2..6), Substr::from("name"));
+        assert_ne!(Substr::new(&source, 2..6), Substr::new(&source, 0..6));
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_char_boundary() {
+        Substr::new(&Arc::from("ä"), 0..1);
+    }
+}
@@ -0,0 +1,62 @@
+use std::{fmt::Debug, sync::Arc};
+
+use crate::value::Value;
+
+/// Handling of variables missing from the variable container and of unregistered modifiers.
+///
+/// Independent of this setting, a value followed by the `default`, `is_defined` or `is_null`
+/// modifier never fails because of a missing variable, e.g. `{name|default:"n/a"}`.
+#[derive(Clone, Default)]
+pub enum Undefined {
+    /// Rendering fails with [`crate::RenderErrorKind::UnknownVariable`] or
+    /// [`crate::RenderErrorKind::UnknownModifier`]
+    #[default]
+    Error,
+    /// An expression with a missing variable or an unknown modifier is [`Value::Null`]. Its
+    /// remaining modifiers and operators are skipped, so `{missing + 1}` prints an empty string.
+    /// Undefined expressions are false in conditions and loop over no items. Missing modifier
+    /// arguments are replaced by [`Value::Null`].
+    Null,
+    /// Like [`Undefined::Null`] but uses an empty string and logs a warning
+    Empty,
+    /// Prints the tag like `{name|upper}` unchanged and logs a warning. In conditions and loops
+    /// the expression is handled like [`Undefined::Null`], and an assignment stores the text of
+    /// the expression.
+    Keep,
+    /// Calls the function to get a replacement. Remaining modifiers are applied to the returned
+    /// value. Rendering fails if it returns `None`.
+    Callback(Arc<UndefinedCallback>),
+}
+
+pub type UndefinedCallback = dyn Fn(Unknown) -> Option<Value> + Send + Sync;
+
+impl Undefined {
+    /// Creates a [`Undefined::Callback`].
+    pub fn callback<F>(f: F) -> Self
+    where
+        F: Fn(Unknown) -> Option<Value> + Send + Sync + 'static,
+    {
+        Self::Callback(Arc::new(f))
+    }
+}
+
+impl Debug for Undefined {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error => write!(f, "Error"),
+            Self::Null => write!(f, "Null"),
+            Self::Empty => write!(f, "Empty"),
+            Self::Keep => write!(f, "Keep"),
+            Self::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+/// A name used in a template that could not be resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unknown<'a> {
+    /// A variable or a path like `user.name` that is missing
+    Variable(&'a str),
+    /// A modifier that is not registered
+    Modifier(&'a str),
+}
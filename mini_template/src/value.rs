@@ -1,14 +1,39 @@
-use std::convert::TryFrom;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fmt::Display,
+};
+
+#[cfg(feature = "serde")]
+mod de;
+#[cfg(feature = "serde")]
+pub mod ser;
+
+#[cfg(feature = "serde")]
+pub use ser::to_value;
 
 /// Values are used as variables inside a template.
+///
+/// Arrays and maps are only equal to and ordered against values of the same kind. Comparing
+/// them with a string, a number or null is always false.
 #[derive(Debug, Clone)]
 pub enum Value {
     /// Stores a string
     String(String),
-    /// Stores a number as f64
+    /// Stores a floating point number. Integral numbers are printed without a decimal point,
+    /// others with the shortest representation that parses back to the same number.
     Number(f64),
+    /// Stores an integer without losing precision
+    Integer(i128),
     /// Stores a boolean
     Bool(bool),
+    /// Stores a list of values
+    Array(Vec<Value>),
+    /// Stores named values. Templates have no map literal, maps are only passed in as data.
+    Map(BTreeMap<String, Value>),
+    /// No value. It is printed as an empty string and is false in conditions.
+    Null,
 }
 
 impl Value {
@@ -17,7 +42,11 @@ impl Value {
         match self {
             Self::Bool(b) => *b,
             Self::Number(n) => *n != 0.,
+            Self::Integer(i) => *i != 0,
             Self::String(s) => !s.is_empty(),
+            Self::Array(a) => !a.is_empty(),
+            Self::Map(m) => !m.is_empty(),
+            Self::Null => false,
         }
     }
 }
@@ -27,11 +56,20 @@ impl PartialEq for Value {
         match (self, other) {
             (Self::String(s), Self::String(o)) => s == o,
             (Self::Number(s), Self::Number(o)) => s == o,
+            (Self::Integer(s), Self::Integer(o)) => s == o,
+            (Self::Integer(s), Self::Number(o)) | (Self::Number(o), Self::Integer(s)) => {
+                cmp_integer_number(*s, *o) == Some(Ordering::Equal)
+            }
             (Self::Bool(s), Self::Bool(o)) => s == o,
             (Self::Bool(s), o) => *s == o.as_bool(),
             (s, Self::Bool(o)) => s.as_bool() == *o,
+            (Self::Null, o) | (o, Self::Null) => matches!(o, Self::Null),
             (Self::String(s), Self::Number(o)) => s == &o.to_string(),
             (Self::Number(s), Self::String(o)) => &s.to_string() == o,
+            (Self::Array(s), Self::Array(o)) => s == o,
+            (Self::Map(s), Self::Map(o)) => s == o,
+            (Self::Array(_) | Self::Map(_), _) | (_, Self::Array(_) | Self::Map(_)) => false,
+            (s, o) => s.to_string() == o.to_string(),
         }
     }
 }
@@ -42,11 +80,22 @@ impl PartialOrd for Value {
         match (self, other) {
             (Self::String(s), Self::String(o)) => s < o,
             (Self::Number(s), Self::Number(o)) => s < o,
+            (Self::Integer(s), Self::Integer(o)) => s < o,
+            (Self::Integer(s), Self::Number(o)) => {
+                cmp_integer_number(*s, *o).is_some_and(Ordering::is_lt)
+            }
+            (Self::Number(s), Self::Integer(o)) => {
+                cmp_integer_number(*o, *s).is_some_and(Ordering::is_gt)
+            }
             (Self::Bool(s), Self::Bool(o)) => s < o,
             (Self::Bool(s), o) => *s < o.as_bool(),
             (s, Self::Bool(o)) => s.as_bool() < *o,
             (Self::String(s), Self::Number(o)) => s < &o.to_string(),
             (Self::Number(s), Self::String(o)) => &s.to_string() < o,
+            (Self::Array(s), Self::Array(o)) => s < o,
+            (Self::Map(s), Self::Map(o)) => s < o,
+            (Self::Array(_) | Self::Map(_), _) | (_, Self::Array(_) | Self::Map(_)) => false,
+            (s, o) => s.to_string() < o.to_string(),
         }
     }
 
@@ -54,11 +103,22 @@ impl PartialOrd for Value {
         match (self, other) {
             (Self::String(s), Self::String(o)) => s >= o,
             (Self::Number(s), Self::Number(o)) => s >= o,
+            (Self::Integer(s), Self::Integer(o)) => s >= o,
+            (Self::Integer(s), Self::Number(o)) => {
+                cmp_integer_number(*s, *o).is_some_and(Ordering::is_ge)
+            }
+            (Self::Number(s), Self::Integer(o)) => {
+                cmp_integer_number(*o, *s).is_some_and(Ordering::is_le)
+            }
             (Self::Bool(s), Self::Bool(o)) => s >= o,
             (Self::Bool(s), o) => *s == o.as_bool(),
             (s, Self::Bool(o)) => s.as_bool() >= *o,
             (Self::String(s), Self::Number(o)) => s >= &o.to_string(),
             (Self::Number(s), Self::String(o)) => &s.to_string() >= o,
+            (Self::Array(s), Self::Array(o)) => s >= o,
+            (Self::Map(s), Self::Map(o)) => s >= o,
+            (Self::Array(_) | Self::Map(_), _) | (_, Self::Array(_) | Self::Map(_)) => false,
+            (s, o) => s.to_string() >= o.to_string(),
         }
     }
 
@@ -66,11 +126,22 @@ impl PartialOrd for Value {
         match (self, other) {
             (Self::String(s), Self::String(o)) => s > o,
             (Self::Number(s), Self::Number(o)) => s > o,
+            (Self::Integer(s), Self::Integer(o)) => s > o,
+            (Self::Integer(s), Self::Number(o)) => {
+                cmp_integer_number(*s, *o).is_some_and(Ordering::is_gt)
+            }
+            (Self::Number(s), Self::Integer(o)) => {
+                cmp_integer_number(*o, *s).is_some_and(Ordering::is_lt)
+            }
             (Self::Bool(s), Self::Bool(o)) => s > o,
             (Self::Bool(s), o) => *s > o.as_bool(),
             (s, Self::Bool(o)) => s.as_bool() > *o,
             (Self::String(s), Self::Number(o)) => s > &o.to_string(),
             (Self::Number(s), Self::String(o)) => &s.to_string() > o,
+            (Self::Array(s), Self::Array(o)) => s > o,
+            (Self::Map(s), Self::Map(o)) => s > o,
+            (Self::Array(_) | Self::Map(_), _) | (_, Self::Array(_) | Self::Map(_)) => false,
+            (s, o) => s.to_string() > o.to_string(),
         }
     }
 
@@ -78,25 +149,62 @@ impl PartialOrd for Value {
         match (self, other) {
             (Self::String(s), Self::String(o)) => s <= o,
             (Self::Number(s), Self::Number(o)) => s <= o,
+            (Self::Integer(s), Self::Integer(o)) => s <= o,
+            (Self::Integer(s), Self::Number(o)) => {
+                cmp_integer_number(*s, *o).is_some_and(Ordering::is_le)
+            }
+            (Self::Number(s), Self::Integer(o)) => {
+                cmp_integer_number(*o, *s).is_some_and(Ordering::is_ge)
+            }
             (Self::Bool(s), Self::Bool(o)) => s <= o,
             (Self::Bool(s), o) => *s <= o.as_bool(),
             (s, Self::Bool(o)) => s.as_bool() <= *o,
             (Self::String(s), Self::Number(o)) => s <= &o.to_string(),
             (Self::Number(s), Self::String(o)) => &s.to_string() <= o,
+            (Self::Array(s), Self::Array(o)) => s <= o,
+            (Self::Map(s), Self::Map(o)) => s <= o,
+            (Self::Array(_) | Self::Map(_), _) | (_, Self::Array(_) | Self::Map(_)) => false,
+            (s, o) => s.to_string() <= o.to_string(),
         }
     }
 
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         if self == other {
-            Some(std::cmp::Ordering::Equal)
+            Some(Ordering::Equal)
         } else if self < other {
-            Some(std::cmp::Ordering::Less)
+            Some(Ordering::Less)
+        } else if self > other {
+            Some(Ordering::Greater)
         } else {
-            Some(std::cmp::Ordering::Greater)
+            None
         }
     }
 }
 
+/// Compares an integer with a number without rounding either of them. Converting the integer
+/// to `f64` would lose precision above 2^53.
+fn cmp_integer_number(integer: i128, number: f64) -> Option<Ordering> {
+    // i128::MIN is -2^127 and exactly representable, i128::MAX rounds up to 2^127
+    const BOUND: f64 = i128::MIN as f64;
+    if number.is_nan() {
+        None
+    } else if number >= -BOUND {
+        Some(Ordering::Less)
+    } else if number < BOUND {
+        Some(Ordering::Greater)
+    } else {
+        // The truncated number fits into an i128, the fraction decides between equal values
+        let fract = number.fract();
+        Some(integer.cmp(&(number as i128)).then(if fract > 0. {
+            Ordering::Less
+        } else if fract < 0. {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }))
+    }
+}
+
 /// Error type for mismatched types.
 ///
 /// This error type is used if the expected value type and the given value type do not match.
@@ -106,18 +214,43 @@ pub struct TypeError {
     pub expected_type: &'static str,
 }
 
-impl ToString for Value {
-    fn to_string(&self) -> String {
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::String(s) => s.to_owned(),
-            Self::Number(n) => n.to_string(),
+            Self::String(s) => write!(f, "{}", s),
+            // -0 is printed as 0
+            Self::Number(n) if *n == 0. => write!(f, "0"),
+            Self::Number(n) if n.fract() == 0. => write!(f, "{:.0}", n),
+            Self::Number(n) => write!(f, "{}", n),
+            Self::Integer(i) => write!(f, "{}", i),
             Self::Bool(b) => {
                 if *b {
-                    String::from("true")
+                    write!(f, "true")
                 } else {
-                    String::from("false")
+                    write!(f, "false")
+                }
+            }
+            Self::Array(a) => {
+                write!(f, "[")?;
+                for (i, v) in a.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
                 }
+                write!(f, "]")
             }
+            Self::Map(m) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in m.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", k, v)?;
+                }
+                write!(f, "}}")
+            }
+            Self::Null => Ok(()),
         }
     }
 }
@@ -138,6 +271,81 @@ impl<'a> TryFrom<&'a Value> for &'a str {
     }
 }
 
+impl<'a> TryFrom<&'a Value> for &'a [Value] {
+    type Error = TypeError;
+    fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(a) => Ok(&a[..]),
+            _ => Err(TypeError {
+                expected_type: stringify!(&[Value]),
+                storage_type: stringify!(Array),
+            }),
+        }
+    }
+}
+
+impl TryFrom<&Value> for f64 {
+    type Error = TypeError;
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            Value::Integer(i) => Ok(*i as f64),
+            _ => Err(TypeError {
+                expected_type: stringify!(f64),
+                storage_type: stringify!(Number),
+            }),
+        }
+    }
+}
+
+/// Integers are converted from [`Value::Integer`] if they fit into the type and from
+/// [`Value::Number`] by truncating.
+macro_rules! integer_impl {
+    ($($type: ty),+) => {
+        $(
+            impl TryFrom<&Value> for $type {
+                type Error = TypeError;
+                fn try_from(value: &Value) -> Result<Self, Self::Error> {
+                    let error = TypeError {
+                        expected_type: stringify!($type),
+                        storage_type: stringify!(Integer),
+                    };
+                    match value {
+                        Value::Integer(i) => <$type>::try_from(*i).map_err(|_| error),
+                        Value::Number(n) => Ok(*n as $type),
+                        _ => Err(error),
+                    }
+                }
+            }
+
+            impl From<$type> for Value {
+                #[allow(clippy::unnecessary_cast)]
+                fn from(i: $type) -> Self {
+                    Self::Integer(i as i128)
+                }
+            }
+        )+
+    };
+}
+
+impl From<HashMap<String, Value>> for Value {
+    fn from(m: HashMap<String, Value>) -> Self {
+        Self::Map(m.into_iter().collect())
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(v: Vec<T>) -> Self {
+        Self::Array(v.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(o: Option<T>) -> Self {
+        o.map_or(Self::Null, Into::into)
+    }
+}
+
 macro_rules! value_impl {
     ($name: ident => $main_type: ty as [$($type: ty),+]) => {
         value_impl!($name => $main_type);
@@ -187,4 +395,58 @@ macro_rules! value_impl {
 
 value_impl!(String => String);
 value_impl!(Bool => bool);
-value_impl!(Number => f64 as [isize, i32, usize, u32]);
+value_impl!(from_value Number => f64);
+integer_impl!(isize, i32, usize, u32, i64, u64, i128);
+value_impl!(try_from_type Array => Vec<Value>);
+value_impl!(Map => BTreeMap<String, Value>);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::Value;
+
+    #[test]
+    fn containers_do_not_equal_scalars() {
+        let array = Value::Array(vec![Value::Integer(1)]);
+        let map = Value::Map(BTreeMap::from_iter([("a".to_owned(), Value::Integer(1))]));
+        assert_ne!(array, Value::String("[1]".to_owned()));
+        assert_ne!(Value::String("[1]".to_owned()), array);
+        assert_ne!(map, Value::String("{a: 1}".to_owned()));
+        assert_ne!(array, map);
+        assert_eq!(array, Value::Array(vec![Value::Number(1.)]));
+    }
+
+    #[test]
+    fn containers_are_not_ordered_against_scalars() {
+        let array = Value::Array(vec![Value::Integer(1)]);
+        let string = Value::String("[0]".to_owned());
+        assert!(!array.lt(&string));
+        assert!(!array.gt(&string));
+        assert!(!array.le(&string));
+        assert!(!array.ge(&string));
+        assert_eq!(array.partial_cmp(&string), None);
+        assert!(array < Value::Array(vec![Value::Integer(2)]));
+    }
+
+    #[test]
+    fn integers_and_numbers_compare_exactly() {
+        let large = Value::Integer(9007199254740993);
+        let number = Value::Number(9007199254740992.);
+        assert_ne!(large, number);
+        assert_ne!(number, large);
+        assert!(large > number);
+        assert!(number < large);
+        assert!(large >= number);
+        assert!(!large.le(&number));
+        assert_eq!(Value::Integer(9007199254740992), number);
+
+        assert!(Value::Integer(2) < Value::Number(2.5));
+        assert!(Value::Integer(-2) > Value::Number(-2.5));
+        assert!(Value::Integer(i128::MAX) < Value::Number(f64::INFINITY));
+        assert!(Value::Integer(i128::MAX) < Value::Number(i128::MAX as f64));
+        assert!(Value::Integer(i128::MIN) > Value::Number(f64::MIN));
+        assert_eq!(Value::Integer(i128::MIN), Value::Number(i128::MIN as f64));
+        assert_eq!(Value::Integer(1).partial_cmp(&Value::Number(f64::NAN)), None);
+    }
+}
@@ -1,7 +1,12 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use crate::value::Value;
+use once_cell::unsync::Lazy;
 
+use crate::{value::Value, RenderErrorKind};
+
+pub use mini_template_macro::IntoVariables;
+
+/// Storage for the variables of a template.
 pub trait VariableContainer {
     fn get(&self, k: &str) -> Option<&Value>;
     fn get_mut(&mut self, k: &str) -> Option<&mut Value>;
@@ -21,3 +26,463 @@ impl VariableContainer for HashMap<String, Value> {
         self.insert(k, v);
     }
 }
+
+/// Variables of a render split into scopes on top of the rendered data.
+///
+/// Reads look through the scopes from the innermost to the outermost and fall back to the data
+/// and then to the globals. Writes update the innermost scope containing the variable and
+/// otherwise go into the innermost scope, so assignments never change the rendered data and
+/// variables first assigned inside a loop, include or block are gone once it is left.
+/// Updating outer variables instead of shadowing them lets `{while}` bodies change the
+/// variables of their condition.
+pub struct Scopes<VC: VariableContainer> {
+    data: VC,
+    /// Never empty. The first scope holds the assignments made outside of any block.
+    scopes: Vec<HashMap<String, Value>>,
+    globals: Arc<HashMap<String, Value>>,
+}
+
+impl<VC: VariableContainer> Scopes<VC> {
+    pub fn new(data: VC) -> Self {
+        Self {
+            data,
+            scopes: vec![HashMap::new()],
+            globals: Arc::default(),
+        }
+    }
+
+    /// Sets the variables used if neither a scope nor the data contains a variable.
+    pub fn set_globals(&mut self, globals: Arc<HashMap<String, Value>>) {
+        self.globals = globals;
+    }
+
+    /// Starts a new innermost scope.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Removes the innermost scope and all variables assigned in it. The outermost scope is
+    /// never removed.
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Sets a variable in the innermost scope. Variables with the same name in outer scopes are
+    /// hidden until the scope is removed.
+    pub fn set_local(&mut self, k: String, v: Value) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(k, v);
+        }
+    }
+
+    /// Returns the rendered data without any assignments.
+    pub fn data(&self) -> &VC {
+        &self.data
+    }
+
+    /// Returns the variable of the innermost scope containing it.
+    fn get_scoped_mut(&mut self, k: &str) -> Option<&mut Value> {
+        self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(k))
+    }
+}
+
+impl<VC: VariableContainer> VariableContainer for Scopes<VC> {
+    fn get(&self, k: &str) -> Option<&Value> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(k))
+            .or_else(|| self.data.get(k))
+            .or_else(|| self.globals.get(k))
+    }
+
+    /// Returns the variable of the innermost scope containing it. A variable of the data or the
+    /// globals is copied into the innermost scope before it is changed.
+    fn get_mut(&mut self, k: &str) -> Option<&mut Value> {
+        if self.get_scoped_mut(k).is_none() {
+            let value = self.get(k)?.clone();
+            self.set_local(k.to_owned(), value);
+        }
+        self.get_scoped_mut(k)
+    }
+
+    /// Updates the variable in the innermost scope containing it. Other variables are set in the
+    /// innermost scope.
+    fn set(&mut self, k: String, v: Value) {
+        match self.get_scoped_mut(&k) {
+            Some(value) => *value = v,
+            None => self.set_local(k, v),
+        }
+    }
+}
+
+/// A function computing the value of a lazy variable
+pub type LazyValue<'a> = Box<dyn FnOnce() -> Value + 'a>;
+
+/// Variables whose values are computed when a template reads them for the first time.
+///
+/// Useful for data that is expensive to compute and not used by every template. Every function
+/// is called at most once and not at all if no template reads the variable. Lazy variables take
+/// precedence over the wrapped variables.
+/// ```
+/// use mini_template::{value::Value, variable_container::LazyVariables, MiniTemplate};
+///
+/// let mut mini_template = MiniTemplate::default();
+/// mini_template.add_template(0, "{visits} visits".to_owned()).unwrap();
+///
+/// let mut variables = LazyVariables::new();
+/// variables.insert_lazy("visits".to_owned(), || Value::from(42));
+/// variables.insert_lazy("unused".to_owned(), || unreachable!());
+/// assert_eq!(mini_template.render(&0, variables).unwrap(), "42 visits");
+/// ```
+pub struct LazyVariables<'a, VC: VariableContainer = HashMap<String, Value>> {
+    variables: VC,
+    lazy: HashMap<String, Lazy<Value, LazyValue<'a>>>,
+}
+
+impl LazyVariables<'_> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<'a, VC: VariableContainer> LazyVariables<'a, VC> {
+    /// Adds lazy variables to `variables`.
+    pub fn with_variables(variables: VC) -> Self {
+        Self {
+            variables,
+            lazy: HashMap::new(),
+        }
+    }
+
+    /// Adds a variable computed by `f` when it is read for the first time.
+    pub fn insert_lazy<F: FnOnce() -> Value + 'a>(&mut self, key: String, f: F) {
+        self.lazy.insert(key, Lazy::new(Box::new(f)));
+    }
+}
+
+impl<VC: VariableContainer + Default> Default for LazyVariables<'_, VC> {
+    fn default() -> Self {
+        Self::with_variables(VC::default())
+    }
+}
+
+impl<VC: VariableContainer> VariableContainer for LazyVariables<'_, VC> {
+    fn get(&self, k: &str) -> Option<&Value> {
+        match self.lazy.get(k) {
+            Some(lazy) => Some(Lazy::force(lazy)),
+            None => self.variables.get(k),
+        }
+    }
+
+    fn get_mut(&mut self, k: &str) -> Option<&mut Value> {
+        match self.lazy.get_mut(k) {
+            Some(lazy) => Some(&mut **lazy),
+            None => self.variables.get_mut(k),
+        }
+    }
+
+    /// Replaces a lazy variable without computing it.
+    fn set(&mut self, k: String, v: Value) {
+        self.lazy.remove(&k);
+        self.variables.set(k, v);
+    }
+}
+
+/// Data a template can be rendered with.
+///
+/// Implemented for every [`VariableContainer`], for [`IntoVariables`] and, with the `serde`
+/// feature, for references to types implementing `serde::Serialize`. `M` only tells these
+/// implementations apart and is always inferred.
+pub trait IntoVariableContainer<M> {
+    type Container: VariableContainer;
+
+    /// # Error
+    /// Returns [`RenderErrorKind::Serialize`] if the data can not be converted.
+    fn into_variable_container(self) -> Result<Self::Container, RenderErrorKind>;
+}
+
+/// Marks the [`IntoVariableContainer`] implementation for [`VariableContainer`]s.
+pub enum Container {}
+
+impl<VC: VariableContainer> IntoVariableContainer<Container> for VC {
+    type Container = VC;
+
+    fn into_variable_container(self) -> Result<VC, RenderErrorKind> {
+        Ok(self)
+    }
+}
+
+/// A struct whose fields are template variables.
+///
+/// Implement it with `#[derive(IntoVariables)]`. Every field is converted with
+/// `Into<Value>`. Fields can be configured with the `variable` attribute:
+/// * `#[variable(rename = "name")]` uses another variable name
+/// * `#[variable(skip)]` ignores the field
+/// * `#[variable(flatten)]` adds the variables of a nested struct instead of a map
+///
+/// Nested structs deriving `IntoVariables` are maps. The fields are moved into a new
+/// container when rendering, so assignments like `{x = 1}` never change the struct.
+/// [`IntoVariables::into_variable_map`] creates that container to combine the fields with
+/// [`LazyVariables`] or [`Scopes`].
+/// ```
+/// use mini_template::{variable_container::IntoVariables, MiniTemplate};
+///
+/// #[derive(IntoVariables)]
+/// struct Author {
+///     name: String,
+/// }
+///
+/// #[derive(IntoVariables)]
+/// struct Post {
+///     title: String,
+///     #[variable(rename = "written_by")]
+///     author: Author,
+///     #[variable(skip)]
+///     draft: bool,
+/// }
+///
+/// let mut mini_template = MiniTemplate::default();
+/// mini_template
+///     .add_template(0, "{title} by {written_by.name}".to_owned())
+///     .unwrap();
+/// let post = Post {
+///     title: "Hello".to_owned(),
+///     author: Author { name: "Ferris".to_owned() },
+///     draft: false,
+/// };
+/// assert_eq!(mini_template.render(&0, post).unwrap(), "Hello by Ferris");
+/// ```
+pub trait IntoVariables {
+    /// Inserts the fields into `variables`.
+    fn into_variables(self, variables: &mut HashMap<String, Value>);
+
+    /// Returns the fields as a [`VariableContainer`].
+    fn into_variable_map(self) -> HashMap<String, Value>
+    where
+        Self: Sized,
+    {
+        let mut variables = HashMap::new();
+        self.into_variables(&mut variables);
+        variables
+    }
+}
+
+/// Marks the [`IntoVariableContainer`] implementation for [`IntoVariables`].
+pub enum Fields {}
+
+impl<T: IntoVariables> IntoVariableContainer<Fields> for T {
+    type Container = HashMap<String, Value>;
+
+    fn into_variable_container(self) -> Result<Self::Container, RenderErrorKind> {
+        Ok(self.into_variable_map())
+    }
+}
+
+/// Marks the [`IntoVariableContainer`] implementation for `Serialize` types.
+#[cfg(feature = "serde")]
+pub enum Serialized {}
+
+/// The data must serialize to a struct or map. Its fields are the variables.
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + ?Sized> IntoVariableContainer<Serialized> for &T {
+    type Container = HashMap<String, Value>;
+
+    fn into_variable_container(self) -> Result<Self::Container, RenderErrorKind> {
+        match crate::value::to_value(self) {
+            Ok(Value::Map(map)) => Ok(map.into_iter().collect()),
+            Ok(_) => Err(RenderErrorKind::Serialize(
+                "data must be a struct or map".to_owned(),
+            )),
+            Err(e) => Err(RenderErrorKind::Serialize(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::HashMap};
+
+    use super::{IntoVariables, LazyVariables, Scopes, VariableContainer};
+    use crate::value::Value;
+
+    #[derive(IntoVariables)]
+    struct Address {
+        city: String,
+    }
+
+    #[derive(IntoVariables)]
+    struct Meta<T: Into<Value>> {
+        id: T,
+    }
+
+    #[derive(IntoVariables)]
+    struct Customer {
+        #[variable(rename = "customer_name")]
+        name: String,
+        address: Address,
+        #[variable(flatten)]
+        meta: Meta<u32>,
+        #[variable(skip)]
+        #[allow(dead_code)]
+        password: String,
+        tags: Vec<String>,
+    }
+
+    fn customer() -> Customer {
+        Customer {
+            name: "Ferris".to_owned(),
+            address: Address {
+                city: "Berlin".to_owned(),
+            },
+            meta: Meta { id: 7 },
+            password: "secret".to_owned(),
+            tags: vec!["crab".to_owned()],
+        }
+    }
+
+    #[test]
+    fn derive_into_variables() {
+        let mut variables = HashMap::new();
+        customer().into_variables(&mut variables);
+        let mut keys = variables.keys().map(String::as_str).collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, ["address", "customer_name", "id", "tags"]);
+        assert_eq!(variables["id"], Value::Integer(7));
+        assert_eq!(
+            variables["tags"],
+            Value::Array(vec![Value::String("crab".to_owned())])
+        );
+    }
+
+    #[cfg(feature = "assign")]
+    #[test]
+    fn render_derived() {
+        let mut mini_template = crate::MiniTemplate::default();
+        mini_template
+            .add_template(
+                0,
+                "{customer_name = \"Crab\"}{customer_name} {address.city} {id}".to_owned(),
+            )
+            .unwrap();
+        assert_eq!(
+            mini_template.render(&0, customer()).unwrap(),
+            "Crab Berlin 7"
+        );
+    }
+
+    #[test]
+    fn derived_lazy_variables() {
+        let mut mini_template = crate::MiniTemplate::default();
+        mini_template
+            .add_template(0, "{customer_name} {greeting}".to_owned())
+            .unwrap();
+        let mut variables = LazyVariables::with_variables(customer().into_variable_map());
+        variables.insert_lazy("greeting".to_owned(), || Value::from("Hi".to_owned()));
+        assert_eq!(mini_template.render(&0, variables).unwrap(), "Ferris Hi");
+    }
+
+    #[test]
+    fn scopes() {
+        let mut data = HashMap::new();
+        data.insert("name".to_owned(), Value::from("Ferris".to_owned()));
+        let mut scopes = Scopes::new(data);
+        scopes.push_scope();
+        scopes.set("name".to_owned(), Value::from("Crab".to_owned()));
+        scopes.set("inner".to_owned(), Value::Bool(true));
+        assert_eq!(scopes.get("name"), Some(&Value::from("Crab".to_owned())));
+
+        scopes.push_scope();
+        *scopes.get_mut("inner").unwrap() = Value::Bool(false);
+        scopes.set_local("name".to_owned(), Value::Null);
+        scopes.set("name".to_owned(), Value::Integer(1));
+        scopes.pop_scope();
+        assert_eq!(scopes.get("inner"), Some(&Value::Bool(false)));
+        assert_eq!(scopes.get("name"), Some(&Value::from("Crab".to_owned())));
+
+        scopes.pop_scope();
+        scopes.pop_scope();
+        assert_eq!(scopes.get("name"), Some(&Value::from("Ferris".to_owned())));
+        assert_eq!(scopes.get("inner"), None);
+        scopes.set("outer".to_owned(), Value::Null);
+        assert_eq!(scopes.get("outer"), Some(&Value::Null));
+        assert_eq!(scopes.data().get("outer"), None);
+    }
+
+    #[cfg(all(feature = "foreach", feature = "assign"))]
+    #[test]
+    fn loop_scope() {
+        let mut mini_template = crate::MiniTemplate::default();
+        mini_template
+            .add_template(
+                0,
+                "{foreach item in items}{last = item}{last}{endforeach}[{last|default:\"-\"}]"
+                    .to_owned(),
+            )
+            .unwrap();
+        let mut data = HashMap::new();
+        data.insert(
+            "items".to_owned(),
+            Value::from(vec![Value::Integer(1), Value::Integer(2)]),
+        );
+        assert_eq!(mini_template.render(&0, data).unwrap(), "12[-]");
+    }
+
+    #[cfg(all(feature = "loop", feature = "foreach"))]
+    #[test]
+    fn assign_outer_variable() {
+        let mut mini_template = crate::MiniTemplate::default();
+        mini_template
+            .add_template(0, "{i=0}{while i<3}{i=i+1}{endwhile}{i}".to_owned())
+            .unwrap();
+        mini_template
+            .add_template(
+                1,
+                "{sum=0}{foreach item in items}{sum=sum+item}{endforeach}{sum}".to_owned(),
+            )
+            .unwrap();
+        let mut data = HashMap::new();
+        data.insert(
+            "items".to_owned(),
+            Value::from(vec![Value::Integer(1), Value::Integer(2)]),
+        );
+        assert_eq!(mini_template.render(&0, HashMap::new()).unwrap(), "3");
+        assert_eq!(mini_template.render(&1, data).unwrap(), "3");
+    }
+
+    #[test]
+    fn lazy_variables() {
+        let mut mini_template = crate::MiniTemplate::default();
+        mini_template
+            .add_template(0, "{stats.visits} {stats.visits} {name}".to_owned())
+            .unwrap();
+        let calls = Cell::new(0);
+        let mut variables = LazyVariables::new();
+        variables.set("name".to_owned(), Value::from("Ferris".to_owned()));
+        variables.insert_lazy("stats".to_owned(), || {
+            calls.set(calls.get() + 1);
+            let mut stats = HashMap::new();
+            stats.insert("visits".to_owned(), Value::Integer(42));
+            Value::from(stats)
+        });
+        variables.insert_lazy("unused".to_owned(), || {
+            calls.set(calls.get() + 10);
+            Value::Null
+        });
+        assert_eq!(mini_template.render(&0, variables).unwrap(), "42 42 Ferris");
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn set_replaces_lazy_variable() {
+        let mut variables = LazyVariables::new();
+        variables.insert_lazy("x".to_owned(), || unreachable!());
+        variables.set("x".to_owned(), Value::Bool(true));
+        assert_eq!(variables.get("x"), Some(&Value::Bool(true)));
+        variables.insert_lazy("y".to_owned(), || Value::Integer(1));
+        *variables.get_mut("y").unwrap() = Value::Integer(2);
+        assert_eq!(variables.get("y"), Some(&Value::Integer(2)));
+    }
+}
@@ -0,0 +1,5 @@
+#[test]
+fn into_variables_attribute_errors() {
+    let tests = trybuild::TestCases::new();
+    tests.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,14 @@
+use mini_template::variable_container::IntoVariables;
+
+#[derive(IntoVariables)]
+struct Inner {
+    id: u32,
+}
+
+#[derive(IntoVariables)]
+struct Data {
+    #[variable(flatten, rename = "inner")]
+    inner: Inner,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use mini_template::variable_container::IntoVariables;
+
+#[derive(IntoVariables)]
+struct Data {
+    #[variable]
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use mini_template::variable_container::IntoVariables;
+
+#[derive(IntoVariables)]
+struct Data(String);
+
+fn main() {}
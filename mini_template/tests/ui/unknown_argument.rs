@@ -0,0 +1,9 @@
+use mini_template::variable_container::IntoVariables;
+
+#[derive(IntoVariables)]
+struct Data {
+    #[variable(ignore)]
+    name: String,
+}
+
+fn main() {}
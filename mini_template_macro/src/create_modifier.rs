@@ -61,7 +61,7 @@ pub fn create_modifier(attrs: syn::AttributeArgs, item: syn::ItemFn) -> Result<T
 
     let vars = create_var_init_code(&inputs, &attrs, &mini_template_crate_name)?;
     let inner_fn = &item;
-    let modifier_code_call = modifier_code_call(&item.sig.ident, &inputs.inputs, &attrs, &mini_template_crate_name);
+    let modifier_code_call = modifier_code_call(&item.sig.ident, inputs.inputs, &attrs, &mini_template_crate_name);
 
     if attrs.modifier_ident.is_some() {
         Ok(quote::quote! {
@@ -98,16 +98,22 @@ fn get_mini_template_crate_name(attrs: &Attrs) -> syn::Ident {
         return mini_template_ident.clone()
     }
 
+    find_mini_template_crate()
+}
+
+/// Returns the name `mini_template` is imported as. `mini_template` refers to itself by its own
+/// name, so generated code also works in its tests and doc tests.
+pub fn find_mini_template_crate() -> syn::Ident {
     let found_crate = crate_name("mini_template").expect("my-crate is present in `Cargo.toml`");
     match found_crate {
-        FoundCrate::Itself => syn::Ident::new("crate", proc_macro2::Span::call_site()),
+        FoundCrate::Itself => syn::Ident::new("mini_template", proc_macro2::Span::call_site()),
         FoundCrate::Name(name) => {
             syn::Ident::new(&name, proc_macro2::Span::call_site())
         }
     }
 }
 
-fn modifier_code_call<'a>(ident: &syn::Ident, inputs: &syn::punctuated::Punctuated<syn::FnArg, syn::token::Comma>, attrs: &Attrs, mini_template_crate_name: &syn::Ident) -> TokenStream {
+fn modifier_code_call(ident: &syn::Ident, inputs: &syn::punctuated::Punctuated<syn::FnArg, syn::token::Comma>, attrs: &Attrs, mini_template_crate_name: &syn::Ident) -> TokenStream {
     let inputs = inputs.iter().map(|i| {
         if let syn::FnArg::Typed(syn::PatType {
             pat,
@@ -133,7 +139,7 @@ fn modifier_code_call<'a>(ident: &syn::Ident, inputs: &syn::punctuated::Punctuat
     }
 }
 
-fn create_var_init_code<'a>(
+fn create_var_init_code(
     inputs: &Inputs,
     attrs: &Attrs,
     mini_template_crate_name: &syn::Ident
@@ -305,7 +311,7 @@ impl <'a> Inputs<'a> {
                 if let syn::Pat::Ident(pat_ident) = &*typed.pat {
                     Ok(&pat_ident.ident)
                 } else {
-                    return Err(syn::Error::new(i.span(), "All arguments need to be typed"))
+                    Err(syn::Error::new(i.span(), "All arguments need to be typed"))
                 }
             }).collect::<Result<_, _>>()?,
             inputs: i
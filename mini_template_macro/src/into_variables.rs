@@ -0,0 +1,110 @@
+use proc_macro2::TokenStream;
+use syn::spanned::Spanned;
+
+use crate::create_modifier::find_mini_template_crate;
+
+/// Implements `IntoVariables` and `From<Self> for Value` for a struct with named fields.
+///
+/// Fields are converted with `Into<Value>`. Supported field attributes:
+/// * `#[variable(rename = "name")]` uses another variable name
+/// * `#[variable(skip)]` ignores the field
+/// * `#[variable(flatten)]` adds the variables of a nested struct instead of a map
+pub fn derive_into_variables(item: syn::DeriveInput) -> Result<TokenStream, syn::Error> {
+    let fields = match &item.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => return Err(syn::Error::new(item.span(), "IntoVariables requires a struct with named fields"))
+    };
+    let mini_template_crate_name = find_mini_template_crate();
+
+    let inserts = fields.iter().map(|field| {
+        let attrs = FieldAttrs::new(&field.attrs)?;
+        let ident = field.ident.as_ref().unwrap();
+        if attrs.skip {
+            return Ok(TokenStream::new());
+        }
+        if attrs.flatten {
+            return Ok(quote::quote! {
+                #mini_template_crate_name::variable_container::IntoVariables::into_variables(self.#ident, variables);
+            });
+        }
+        let name = attrs.rename.unwrap_or_else(|| syn::LitStr::new(&ident.to_string(), ident.span()));
+        Ok(quote::quote! {
+            variables.insert(#name.to_owned(), Into::<#mini_template_crate_name::value::Value>::into(self.#ident));
+        })
+    }).collect::<Result<TokenStream, syn::Error>>()?;
+
+    let ident = &item.ident;
+    let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
+    Ok(quote::quote! {
+        impl #impl_generics #mini_template_crate_name::variable_container::IntoVariables for #ident #ty_generics #where_clause {
+            fn into_variables(
+                self,
+                variables: &mut std::collections::HashMap<String, #mini_template_crate_name::value::Value>
+            ) {
+                #inserts
+            }
+        }
+
+        impl #impl_generics From<#ident #ty_generics> for #mini_template_crate_name::value::Value #where_clause {
+            fn from(value: #ident #ty_generics) -> Self {
+                let mut variables = std::collections::HashMap::new();
+                #mini_template_crate_name::variable_container::IntoVariables::into_variables(value, &mut variables);
+                Self::from(variables)
+            }
+        }
+    })
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    rename: Option<syn::LitStr>,
+    skip: bool,
+    flatten: bool
+}
+
+impl FieldAttrs {
+
+    fn new(attrs: &[syn::Attribute]) -> Result<Self, syn::Error> {
+        let mut field_attrs = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("variable")) {
+            let list = match attr.parse_meta()? {
+                syn::Meta::List(list) => list,
+                meta => return Err(syn::Error::new(meta.span(), "expected #[variable(...)]"))
+            };
+            for nested in list.nested {
+                let span = nested.span();
+                match nested {
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("skip") => {
+                        field_attrs.skip = true;
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("flatten") => {
+                        field_attrs.flatten = true;
+                        if field_attrs.rename.is_some() {
+                            return Err(syn::Error::new(span, "flatten can not be combined with rename"));
+                        }
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit,
+                        ..
+                    })) if path.is_ident("rename") => {
+                        if field_attrs.flatten {
+                            return Err(syn::Error::new(span, "flatten can not be combined with rename"));
+                        }
+                        if let syn::Lit::Str(s_lit) = lit {
+                            field_attrs.rename = Some(s_lit);
+                            continue;
+                        }
+                        return Err(syn::Error::new(lit.span(), "rename needs to be string"));
+                    }
+                    nested => return Err(syn::Error::new(nested.span(), "Unknown argument"))
+                }
+            }
+        }
+        Ok(field_attrs)
+    }
+
+}
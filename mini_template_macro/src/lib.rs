@@ -1,4 +1,5 @@
 mod create_modifier;
+mod into_variables;
 
 use proc_macro::TokenStream;
 
@@ -6,9 +7,18 @@ use proc_macro::TokenStream;
 pub fn create_modifier(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let attr = syn::parse_macro_input!(_attr as syn::AttributeArgs);
     let item = syn::parse_macro_input!(item as syn::ItemFn);
-    let result = create_modifier::create_modifier(attr, item.into());
+    let result = create_modifier::create_modifier(attr, item);
     match result {
         Ok(o) => o,
         Err(e) => e.to_compile_error()
     }.into()
 }
+
+#[proc_macro_derive(IntoVariables, attributes(variable))]
+pub fn derive_into_variables(item: TokenStream) -> TokenStream {
+    let item = syn::parse_macro_input!(item as syn::DeriveInput);
+    match into_variables::derive_into_variables(item) {
+        Ok(o) => o,
+        Err(e) => e.to_compile_error()
+    }.into()
+}